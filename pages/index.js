@@ -52,16 +52,37 @@ const getContentByteSize = (str) => new TextEncoder().encode(str).length;
 
 // ── Gutter ───────────────────────────────────────────
 
-const updateGutter = (textarea, gutter, errorLine = null) => {
+// Lines covered by a `{ start, end }` source span; a span ending at the
+// start of a line stops on the line before.
+const spanLines = (span) => {
+  if (!span) return null;
+  const endsAtLineStart = span.end.column === 1 && span.end.line > span.start.line;
+  const last = endsAtLineStart ? span.end.line - 1 : span.end.line;
+  return { first: span.start.line, last };
+};
+
+const updateGutter = (textarea, gutter, errorLine = null, span = null) => {
   const lineCount = textarea.value.split('\n').length;
+  const lines = spanLines(span);
   let html = '';
   for (let i = 1; i <= lineCount; i++) {
-    const cls = i === errorLine ? 'gutter-line gutter-line--error' : 'gutter-line';
+    let cls = 'gutter-line';
+    if (i === errorLine) cls += ' gutter-line--error';
+    else if (lines && i >= lines.first && i <= lines.last) cls += ' gutter-line--span';
     html += `<span class="${cls}">${i}</span>`;
   }
   gutter.innerHTML = html;
 };
 
+// Mark a diff node's value in one editor and scroll it into view.
+const revealSpan = (textarea, gutter, span) => {
+  updateGutter(textarea, gutter, null, span);
+  if (!span) return;
+  const lineHeight = parseFloat(getComputedStyle(textarea).lineHeight);
+  textarea.scrollTop = Math.max(0, (span.start.line - 3) * lineHeight);
+  syncScroll(textarea, gutter);
+};
+
 const syncScroll = (textarea, gutter) => {
   gutter.scrollTop = textarea.scrollTop;
 };
@@ -142,10 +163,11 @@ const clearStorageWarning = (warningDiv) => {
 // ── Diff Tree Rendering ─────────────────────────────
 
 const DIFF_TYPE = {
-  UNCHANGED: 0,
-  ADDITIONS: 1,
-  DELETIONS: 2,
-  MODIFIED: 3,
+  UNCHANGED: 'unchanged',
+  ADDITIONS: 'additions',
+  DELETIONS: 'deletions',
+  MODIFIED: 'modified',
+  MOVED: 'moved',
 };
 
 const formatValue = (value) => {
@@ -159,9 +181,12 @@ const countDiffs = (diffs) => {
   let additions = 0;
   let deletions = 0;
   let modified = 0;
+  let moved = 0;
 
   const walk = (nodes) => {
     for (const node of nodes) {
+      if (node.diff_type === DIFF_TYPE.MOVED) moved++;
+      if (node.tag_changed && node.children.length > 0) modified++;
       if (node.children.length > 0) {
         walk(node.children);
       } else if (node.has_diff) {
@@ -181,7 +206,7 @@ const countDiffs = (diffs) => {
   };
 
   walk(diffs);
-  return { additions, deletions, modified };
+  return { additions, deletions, modified, moved };
 };
 
 const diffTypeClass = (diffType) => {
@@ -192,42 +217,102 @@ const diffTypeClass = (diffType) => {
       return 'diff-row--deletion';
     case DIFF_TYPE.MODIFIED:
       return 'diff-row--modified';
+    case DIFF_TYPE.MOVED:
+      return 'diff-row--moved';
     default:
       return 'diff-row--unchanged';
   }
 };
 
+// A value preceded by its YAML tag, if any, as it would be written.
+const withTag = (tag, value) => (tag ? `${tag} ${formatValue(value)}` : formatValue(value));
+
+const LINE_SIGN = { equal: ' ', insert: '+', delete: '-' };
+
+// A changed multi-line string as a unified diff of its `line_hunks`.
+const renderLineHunks = (node) => {
+  const container = document.createElement('div');
+  container.className = 'diff-lines';
+  const left = String(node.diff.left_value).split(/\r?\n/);
+  const right = String(node.diff.right_value).split(/\r?\n/);
+
+  for (const hunk of node.line_hunks) {
+    const [lines, range] =
+      hunk.change === 'insert' ? [right, hunk.right_lines] : [left, hunk.left_lines];
+    for (const line of lines.slice(range.start, range.end)) {
+      const row = document.createElement('div');
+      row.className = `diff-line diff-line--${hunk.change}`;
+      row.textContent = `${LINE_SIGN[hunk.change]} ${line}`;
+      container.appendChild(row);
+    }
+  }
+
+  return container;
+};
+
+// Fill `el` with a quoted string whose changed char `ranges` are marked.
+const renderInlineChanges = (el, tag, value, ranges) => {
+  const chars = Array.from(value);
+  el.append(tag ? `${tag} "` : '"');
+  let pos = 0;
+  for (const range of ranges) {
+    const mark = document.createElement('mark');
+    mark.className = 'diff-inline';
+    mark.textContent = chars.slice(range.start, range.end).join('');
+    el.append(chars.slice(pos, range.start).join(''), mark);
+    pos = range.end;
+  }
+  el.append(chars.slice(pos).join(''), '"');
+};
+
 const renderDiffValue = (node) => {
+  if (node.line_hunks) return renderLineHunks(node);
+
   const container = document.createElement('span');
   container.className = 'diff-value';
+  const left = withTag(node.diff.left_tag, node.diff.left_value);
+  const right = withTag(node.diff.right_tag, node.diff.right_value);
 
   switch (node.diff_type) {
     case DIFF_TYPE.ADDITIONS: {
-      container.textContent = formatValue(node.diff.right_value);
+      container.textContent = right;
       break;
     }
     case DIFF_TYPE.DELETIONS: {
-      container.textContent = formatValue(node.diff.left_value);
+      container.textContent = left;
       break;
     }
     case DIFF_TYPE.MODIFIED: {
-      const left = document.createElement('span');
-      left.className = 'diff-value--left';
-      left.textContent = formatValue(node.diff.left_value);
+      const leftSpan = document.createElement('span');
+      leftSpan.className = 'diff-value--left';
+      const changes = node.inline_changes;
+      if (changes) {
+        renderInlineChanges(leftSpan, node.diff.left_tag, node.diff.left_value, changes.left);
+      } else {
+        leftSpan.textContent = left;
+      }
 
       const arrow = document.createElement('span');
       arrow.className = 'diff-arrow';
       arrow.textContent = '\u2192';
 
-      const right = document.createElement('span');
-      right.className = 'diff-value--right';
-      right.textContent = formatValue(node.diff.right_value);
+      const rightSpan = document.createElement('span');
+      rightSpan.className = 'diff-value--right';
+      if (changes) {
+        renderInlineChanges(rightSpan, node.diff.right_tag, node.diff.right_value, changes.right);
+      } else {
+        rightSpan.textContent = right;
+      }
 
-      container.append(left, arrow, right);
+      container.append(leftSpan, arrow, rightSpan);
       break;
     }
     case DIFF_TYPE.UNCHANGED: {
-      container.textContent = formatValue(node.diff.left_value);
+      container.textContent = left;
+      break;
+    }
+    case DIFF_TYPE.MOVED: {
+      container.textContent = right;
       break;
     }
   }
@@ -235,6 +320,27 @@ const renderDiffValue = (node) => {
   return container;
 };
 
+const renderMoveNote = (node) => {
+  const note = document.createElement('span');
+  note.className = 'diff-move-note';
+  note.textContent = `moved ${node.left_index} \u2192 ${node.right_index}`;
+  return note;
+};
+
+const renderReplaceNote = () => {
+  const note = document.createElement('span');
+  note.className = 'diff-replace-note';
+  note.textContent = 'replacement';
+  return note;
+};
+
+const renderTagNote = (node) => {
+  const note = document.createElement('span');
+  note.className = 'diff-tag-note';
+  note.textContent = `tag ${node.diff.left_tag ?? 'none'} \u2192 ${node.diff.right_tag ?? 'none'}`;
+  return note;
+};
+
 const isSingleScalar = (node) =>
   node.children.length === 1 &&
   node.children[0].children.length === 0 &&
@@ -242,6 +348,8 @@ const isSingleScalar = (node) =>
 
 const nodeMatchesFilter = (node, filter) => {
   if (filter === null) return true;
+  if (filter === DIFF_TYPE.MOVED && node.diff_type === DIFF_TYPE.MOVED) return true;
+  if (filter === DIFF_TYPE.MODIFIED && node.tag_changed) return true;
   if (isSingleScalar(node)) return node.children[0].diff_type === filter;
   if (node.children.length > 0) {
     return node.children.some((child) => nodeMatchesFilter(child, filter));
@@ -249,6 +357,9 @@ const nodeMatchesFilter = (node, filter) => {
   return node.diff_type === filter;
 };
 
+// Diff tree rows and summaries → the node they show, to find its spans.
+const rowNodes = new WeakMap();
+
 const renderDiffNode = (node, isRoot = false, filter = null) => {
   if (!nodeMatchesFilter(node, filter)) return null;
 
@@ -261,13 +372,19 @@ const renderDiffNode = (node, isRoot = false, filter = null) => {
       const child = node.children[0];
       const div = document.createElement('div');
       div.className = `diff-row ${diffTypeClass(child.diff_type)}`;
+      rowNodes.set(div, node);
       if (hasKey) {
         const keySpan = document.createElement('span');
         keySpan.className = 'diff-key';
         keySpan.textContent = node.key + ':';
+        keySpan.title = node.dotted_path;
         div.appendChild(keySpan);
       }
       div.appendChild(renderDiffValue(child));
+      if (node.diff_type === DIFF_TYPE.MOVED) {
+        div.classList.replace(diffTypeClass(child.diff_type), diffTypeClass(node.diff_type));
+        div.appendChild(renderMoveNote(node));
+      }
 
       if (isRoot) {
         div.classList.add('diff-node-root');
@@ -288,12 +405,29 @@ const renderDiffNode = (node, isRoot = false, filter = null) => {
 
     const summary = document.createElement('summary');
     summary.className = diffTypeClass(node.diff_type);
+    rowNodes.set(summary, node);
     if (hasKey) {
       const keySpan = document.createElement('span');
       keySpan.className = 'diff-key';
       keySpan.textContent = node.key + ':';
+      keySpan.title = node.dotted_path;
       summary.appendChild(keySpan);
     }
+    if (node.match_key) {
+      const matchSpan = document.createElement('span');
+      matchSpan.className = 'diff-match-key';
+      matchSpan.textContent = `matched by ${node.match_key}`;
+      summary.appendChild(matchSpan);
+    }
+    if (node.tag_changed) {
+      summary.appendChild(renderTagNote(node));
+    }
+    if (node.replaced) {
+      summary.appendChild(renderReplaceNote());
+    }
+    if (node.diff_type === DIFF_TYPE.MOVED) {
+      summary.appendChild(renderMoveNote(node));
+    }
     details.appendChild(summary);
 
     for (const child of node.children) {
@@ -306,11 +440,13 @@ const renderDiffNode = (node, isRoot = false, filter = null) => {
 
   const div = document.createElement('div');
   div.className = `diff-row ${diffTypeClass(node.diff_type)}${isRoot ? ' diff-node-root' : ''}`;
+  rowNodes.set(div, node);
 
   if (hasKey) {
     const keySpan = document.createElement('span');
     keySpan.className = 'diff-key';
     keySpan.textContent = node.key + ':';
+    keySpan.title = node.dotted_path;
     div.appendChild(keySpan);
   }
 
@@ -331,6 +467,7 @@ const renderDiffTree = (diffs, summaryEls, filter = null) => {
   summaryEls.additions.textContent = counts.additions;
   summaryEls.deletions.textContent = counts.deletions;
   summaryEls.modified.textContent = counts.modified;
+  summaryEls.moved.textContent = counts.moved;
 
   const treeEl = document.getElementById('diff-tree');
   treeEl.innerHTML = '';
@@ -397,11 +534,13 @@ ready(() => {
     additions: document.getElementById('diff-additions'),
     deletions: document.getElementById('diff-deletions'),
     modified: document.getElementById('diff-modified'),
+    moved: document.getElementById('diff-moved'),
   };
   const filterBtns = {
     additions: document.getElementById('filter-additions'),
     deletions: document.getElementById('filter-deletions'),
     modified: document.getElementById('filter-modified'),
+    moved: document.getElementById('filter-moved'),
   };
 
   let lastDiffData = null;
@@ -492,6 +631,7 @@ ready(() => {
     filterBtns.additions.classList.remove('diff-filter--active');
     filterBtns.deletions.classList.remove('diff-filter--active');
     filterBtns.modified.classList.remove('diff-filter--active');
+    filterBtns.moved.classList.remove('diff-filter--active');
   };
 
   // Core diff (async with chunked processing)
@@ -520,7 +660,7 @@ ready(() => {
     await yieldToUI();
 
     try {
-      const diffData = compute_diff(v1, v2);
+      const diffData = compute_diff(v1, v2).diffs;
 
       showLoader('Rendering...');
       await yieldToUI();
@@ -550,10 +690,19 @@ ready(() => {
 
   const debouncedDiff = debounce(runDiff, 400);
 
+  // Clicking a diff row marks its value in both editors.
+  diffTree.addEventListener('click', (event) => {
+    const row = event.target.closest('.diff-row, summary');
+    const node = row && rowNodes.get(row);
+    if (!node) return;
+    revealSpan(textAreaOne, gutterOne, node.left_span);
+    revealSpan(textAreaTwo, gutterTwo, node.right_span);
+  });
+
   // Filter buttons
   const handleFilterClick = (event) => {
     const btn = event.currentTarget;
-    const filterType = parseInt(btn.dataset.filter, 10);
+    const filterType = btn.dataset.filter;
 
     if (activeFilter === filterType) {
       activeFilter = null;
@@ -572,6 +721,7 @@ ready(() => {
   filterBtns.additions.addEventListener('click', handleFilterClick);
   filterBtns.deletions.addEventListener('click', handleFilterClick);
   filterBtns.modified.addEventListener('click', handleFilterClick);
+  filterBtns.moved.addEventListener('click', handleFilterClick);
 
   // Input events
   const handleInput = (textarea, gutter, warningDiv) => {
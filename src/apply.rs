@@ -0,0 +1,581 @@
+use std::fmt;
+
+use crate::diff::{
+    unwrap_tagged, unwrap_tagged_mut, yaml_key_to_string, DiffReport, DiffType, YamlDiff,
+};
+use crate::merge_keys::MERGE_KEY;
+use crate::patch::{diff_to_guarded_json_patch, PatchOperation};
+use crate::path::escape_pointer_token;
+
+/// Why applying a patch failed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchErrorKind {
+    /// Not a JSON Pointer: nonempty and not starting with `/`, or with a
+    /// `~` not followed by `0` or `1`.
+    InvalidPointer(String),
+    /// Nothing at the path, or at one of its parents.
+    PathNotFound(String),
+    /// A sequence index that is not a number or lies past the end.
+    InvalidIndex { path: String, len: usize },
+    /// The path descends into a scalar.
+    NotAContainer(String),
+    /// `move` into a descendant of its own source.
+    MoveIntoItself { from: String, path: String },
+    /// `test` found a different value.
+    TestFailed {
+        path: String,
+        expected: Box<serde_yml::Value>,
+        actual: Box<serde_yml::Value>,
+    },
+    /// A diff of a multi-document stream, which cannot be replayed onto a
+    /// single document.
+    MultiDocumentDiff,
+    /// A diff made with `<<` merge keys expanded changes a member that the
+    /// mapping at this path only gets from its merge key.
+    ExpandedMergeKey(String),
+}
+
+/// A failed patch, with the position of the offending operation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatchError {
+    pub operation: usize,
+    pub kind: PatchErrorKind,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let to_yaml = |value: &serde_yml::Value| {
+            serde_yml::to_string(value)
+                .map(|s| s.trim_end().to_string())
+                .unwrap_or_else(|_| format!("{value:?}"))
+        };
+        // Rejected as a whole, before any operation ran.
+        if !matches!(
+            self.kind,
+            PatchErrorKind::MultiDocumentDiff | PatchErrorKind::ExpandedMergeKey(_)
+        ) {
+            write!(f, "Patch operation {} failed: ", self.operation)?;
+        }
+        match &self.kind {
+            PatchErrorKind::InvalidPointer(p) => write!(f, "invalid JSON Pointer `{p}`"),
+            PatchErrorKind::PathNotFound(p) => write!(f, "path `{p}` does not exist"),
+            PatchErrorKind::InvalidIndex { path, len } => {
+                write!(
+                    f,
+                    "invalid index at `{path}` for a sequence of length {len}"
+                )
+            }
+            PatchErrorKind::NotAContainer(p) => {
+                write!(f, "`{p}` is neither a mapping nor a sequence")
+            }
+            PatchErrorKind::MoveIntoItself { from, path } => {
+                write!(f, "cannot move `{from}` into its own child `{path}`")
+            }
+            PatchErrorKind::TestFailed {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "test at `{path}` expected `{}` but found `{}`",
+                to_yaml(expected),
+                to_yaml(actual)
+            ),
+            PatchErrorKind::MultiDocumentDiff => write!(
+                f,
+                "Cannot apply a diff of several documents to a single document"
+            ),
+            PatchErrorKind::ExpandedMergeKey(p) => write!(
+                f,
+                "`{p}` is changed through its `<<` merge key, diff with raw merge keys to apply"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// Split a JSON Pointer into unescaped reference tokens.
+fn parse_pointer(pointer: &str) -> Result<Vec<String>, PatchErrorKind> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(rest) = pointer.strip_prefix('/') else {
+        return Err(PatchErrorKind::InvalidPointer(pointer.to_string()));
+    };
+    rest.split('/')
+        .map(|token| {
+            let mut unescaped = String::with_capacity(token.len());
+            let mut chars = token.chars();
+            while let Some(c) = chars.next() {
+                if c != '~' {
+                    unescaped.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('0') => unescaped.push('~'),
+                    Some('1') => unescaped.push('/'),
+                    _ => return Err(PatchErrorKind::InvalidPointer(pointer.to_string())),
+                }
+            }
+            Ok(unescaped)
+        })
+        .collect()
+}
+
+/// The mapping key a reference token names: the string itself, or an
+/// existing non-string key (`1`, `true`) that renders the same.
+fn mapping_key(map: &serde_yml::Mapping, token: &str) -> serde_yml::Value {
+    let key = serde_yml::Value::String(token.to_string());
+    if map.contains_key(&key) {
+        return key;
+    }
+    map.keys()
+        .find(|k| yaml_key_to_string(k) == token)
+        .cloned()
+        .unwrap_or(key)
+}
+
+/// Parse a sequence index token; `-` (one past the end) only when
+/// `allow_end` is set.
+fn sequence_index(token: &str, len: usize, allow_end: bool) -> Option<usize> {
+    if token == "-" {
+        return allow_end.then_some(len);
+    }
+    if token.is_empty() || (token.len() > 1 && token.starts_with('0')) {
+        return None;
+    }
+    let index: usize = token.parse().ok()?;
+    let in_bounds = if allow_end { index <= len } else { index < len };
+    in_bounds.then_some(index)
+}
+
+fn pointer_prefix(tokens: &[String]) -> String {
+    tokens
+        .iter()
+        .map(|t| format!("/{}", escape_pointer_token(t)))
+        .collect()
+}
+
+/// The value the tokens lead to.
+fn resolve<'a>(
+    document: &'a mut serde_yml::Value,
+    tokens: &[String],
+) -> Result<&'a mut serde_yml::Value, PatchErrorKind> {
+    let mut node = document;
+    for (depth, token) in tokens.iter().enumerate() {
+        let here = || pointer_prefix(&tokens[..=depth]);
+        node = match unwrap_tagged_mut(node) {
+            serde_yml::Value::Mapping(map) => {
+                let key = mapping_key(map, token);
+                map.get_mut(&key)
+                    .ok_or_else(|| PatchErrorKind::PathNotFound(here()))?
+            }
+            serde_yml::Value::Sequence(seq) => {
+                let len = seq.len();
+                let index = sequence_index(token, len, false)
+                    .ok_or_else(|| PatchErrorKind::InvalidIndex { path: here(), len })?;
+                &mut seq[index]
+            }
+            _ => {
+                return Err(PatchErrorKind::NotAContainer(pointer_prefix(
+                    &tokens[..depth],
+                )))
+            }
+        };
+    }
+    Ok(node)
+}
+
+fn add(
+    document: &mut serde_yml::Value,
+    path: &str,
+    value: serde_yml::Value,
+) -> Result<(), PatchErrorKind> {
+    let tokens = parse_pointer(path)?;
+    let Some((last, parents)) = tokens.split_last() else {
+        *document = value;
+        return Ok(());
+    };
+    match unwrap_tagged_mut(resolve(document, parents)?) {
+        serde_yml::Value::Mapping(map) => {
+            let key = mapping_key(map, last);
+            map.insert(key, value);
+        }
+        serde_yml::Value::Sequence(seq) => {
+            let len = seq.len();
+            let index =
+                sequence_index(last, len, true).ok_or_else(|| PatchErrorKind::InvalidIndex {
+                    path: path.to_string(),
+                    len,
+                })?;
+            seq.insert(index, value);
+        }
+        _ => return Err(PatchErrorKind::NotAContainer(pointer_prefix(parents))),
+    }
+    Ok(())
+}
+
+fn remove(document: &mut serde_yml::Value, path: &str) -> Result<serde_yml::Value, PatchErrorKind> {
+    let tokens = parse_pointer(path)?;
+    let Some((last, parents)) = tokens.split_last() else {
+        return Ok(std::mem::replace(document, serde_yml::Value::Null));
+    };
+    match unwrap_tagged_mut(resolve(document, parents)?) {
+        serde_yml::Value::Mapping(map) => {
+            let key = mapping_key(map, last);
+            map.shift_remove(&key)
+                .ok_or_else(|| PatchErrorKind::PathNotFound(path.to_string()))
+        }
+        serde_yml::Value::Sequence(seq) => {
+            let len = seq.len();
+            let index =
+                sequence_index(last, len, false).ok_or_else(|| PatchErrorKind::InvalidIndex {
+                    path: path.to_string(),
+                    len,
+                })?;
+            Ok(seq.remove(index))
+        }
+        _ => Err(PatchErrorKind::NotAContainer(pointer_prefix(parents))),
+    }
+}
+
+fn apply_operation(
+    document: &mut serde_yml::Value,
+    operation: &PatchOperation,
+) -> Result<(), PatchErrorKind> {
+    match operation {
+        PatchOperation::Add { path, value } => add(document, path, value.clone()),
+        PatchOperation::Remove { path } => remove(document, path).map(|_| ()),
+        PatchOperation::Replace { path, value } => {
+            *resolve(document, &parse_pointer(path)?)? = value.clone();
+            Ok(())
+        }
+        PatchOperation::Move { from, path } => {
+            if path.starts_with(&format!("{from}/")) {
+                return Err(PatchErrorKind::MoveIntoItself {
+                    from: from.clone(),
+                    path: path.clone(),
+                });
+            }
+            let value = remove(document, from)?;
+            add(document, path, value)
+        }
+        PatchOperation::Copy { from, path } => {
+            let value = resolve(document, &parse_pointer(from)?)?.clone();
+            add(document, path, value)
+        }
+        PatchOperation::Test { path, value } => {
+            let actual = resolve(document, &parse_pointer(path)?)?;
+            // Tags are part of the value, so `!Ref X` fails a test of
+            // `!Sub X`.
+            if actual == value {
+                Ok(())
+            } else {
+                Err(PatchErrorKind::TestFailed {
+                    path: path.clone(),
+                    expected: Box::new(value.clone()),
+                    actual: Box::new(actual.clone()),
+                })
+            }
+        }
+    }
+}
+
+/// Apply RFC 6902 operations in order. Operations are atomic as a whole:
+/// on error the document is left untouched.
+pub fn apply_patch(
+    document: &serde_yml::Value,
+    operations: &[PatchOperation],
+) -> Result<serde_yml::Value, PatchError> {
+    let mut patched = document.clone();
+    for (operation, op) in operations.iter().enumerate() {
+        apply_operation(&mut patched, op).map_err(|kind| PatchError { operation, kind })?;
+    }
+    Ok(patched)
+}
+
+/// Check that every member the diff changes below `value` is written in
+/// its mapping, not merged in by a `<<` key the diff expanded away.
+fn check_merge_keys(
+    value: &serde_yml::Value,
+    children: &[YamlDiff],
+    pointer: &str,
+) -> Result<(), PatchErrorKind> {
+    for child in children.iter().filter(|c| c.has_diff) {
+        let Some(key) = &child.key else { continue };
+        let member = match unwrap_tagged(value) {
+            serde_yml::Value::Mapping(map) => {
+                let member = map.get(mapping_key(map, key));
+                if member.is_none()
+                    && child.diff_type != DiffType::Additions
+                    && map.contains_key(MERGE_KEY)
+                {
+                    return Err(PatchErrorKind::ExpandedMergeKey(format!(
+                        "{pointer}/{}",
+                        escape_pointer_token(key)
+                    )));
+                }
+                member
+            }
+            serde_yml::Value::Sequence(seq) => child.left_index.and_then(|i| seq.get(i)),
+            _ => None,
+        };
+        if let Some(member) = member {
+            let pointer = format!("{pointer}/{}", escape_pointer_token(key));
+            check_merge_keys(member, &child.children, &pointer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Replay a diff tree onto `document`. Every value the diff removes,
+/// replaces or moves is checked against the diff's left side first, so a
+/// document that has drifted from it fails with a `TestFailed` error.
+/// Diffs of multi-document streams, whose top-level nodes are documents,
+/// are rejected with `MultiDocumentDiff`, and diffs that change members
+/// only present once `<<` merge keys are expanded with `ExpandedMergeKey`:
+/// diffs meant to be applied should be made with
+/// `DiffOptions::raw_merge_keys`.
+pub fn apply_diff(
+    document: &serde_yml::Value,
+    diffs: &[YamlDiff],
+) -> Result<serde_yml::Value, PatchError> {
+    let rejected = |kind| PatchError { operation: 0, kind };
+    if diffs.iter().any(|node| node.document.is_some()) {
+        return Err(rejected(PatchErrorKind::MultiDocumentDiff));
+    }
+    check_merge_keys(document, diffs, "").map_err(rejected)?;
+    let operations = match diffs {
+        // A scalar or a change of type at the root compares whole values.
+        [node] if node.key.is_none() => {
+            diff_to_guarded_json_patch(&node.diff.left_tagged(), &node.diff.right_tagged(), diffs)
+        }
+        _ => diff_to_guarded_json_patch(document, document, diffs),
+    };
+    apply_patch(document, &operations)
+}
+
+/// What `apply_patch` was handed: RFC 6902 operations, recognised by the
+/// `op` member of their first element, or a serialized diff, either a
+/// `DiffReport` or its bare `diffs` array.
+pub enum Patch {
+    Operations(Vec<PatchOperation>),
+    Diff(Vec<YamlDiff>),
+}
+
+impl Patch {
+    /// Read a patch from a parsed JSON or YAML array.
+    pub fn from_value(value: serde_yml::Value) -> Result<Self, String> {
+        let diffs = match &value {
+            serde_yml::Value::Sequence(items)
+                if items.first().is_none_or(|first| first.get("op").is_some()) =>
+            {
+                return serde_yml::from_value(value)
+                    .map(Patch::Operations)
+                    .map_err(|e| format!("invalid JSON Patch: {e}"));
+            }
+            serde_yml::Value::Sequence(_) => serde_yml::from_value(value),
+            serde_yml::Value::Mapping(_) => {
+                serde_yml::from_value(value).map(|report: DiffReport| report.diffs)
+            }
+            _ => return Err("a patch must be an array or a diff report".to_string()),
+        };
+        diffs
+            .map(Patch::Diff)
+            .map_err(|e| format!("invalid diff: {e}"))
+    }
+
+    pub fn apply(&self, document: &serde_yml::Value) -> Result<serde_yml::Value, PatchError> {
+        match self {
+            Patch::Operations(operations) => apply_patch(document, operations),
+            Patch::Diff(diffs) => apply_diff(document, diffs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::{yaml_diff, DiffContext};
+    use crate::options::DiffOptions;
+
+    fn yaml(input: &str) -> serde_yml::Value {
+        serde_yml::from_str(input).unwrap()
+    }
+
+    fn ops(input: &str) -> Vec<PatchOperation> {
+        serde_yml::from_str(input).unwrap()
+    }
+
+    fn diff(left: &serde_yml::Value, right: &serde_yml::Value) -> Vec<YamlDiff> {
+        yaml_diff(left, right, &DiffContext::new(&DiffOptions::default())).unwrap()
+    }
+
+    #[test]
+    fn operations_apply_in_order() {
+        let patched = apply_patch(
+            &yaml("a: {b: 1}\nlist: [x, y]\n'c/d': 1\n"),
+            &ops(r#"[
+                {op: add, path: /a/c, value: 2},
+                {op: add, path: /list/-, value: z},
+                {op: add, path: /list/0, value: w},
+                {op: remove, path: /c~1d},
+                {op: replace, path: /a/b, value: 10},
+                {op: move, from: /list/3, path: /first},
+                {op: copy, from: /a, path: /copy},
+                {op: test, path: /copy/c, value: 2}
+            ]"#),
+        )
+        .unwrap();
+        assert_eq!(
+            patched,
+            yaml("a: {b: 10, c: 2}\nlist: [w, x, y]\nfirst: z\ncopy: {b: 10, c: 2}\n")
+        );
+    }
+
+    #[test]
+    fn failures_name_the_operation_and_path() {
+        let document = yaml("a: {b: 1}\nlist: [x]\n");
+        let cases = [
+            (
+                "[{op: test, path: /a/b, value: 2}]",
+                "Patch operation 0 failed: test at `/a/b` expected `2` but found `1`",
+            ),
+            (
+                "[{op: add, path: /a/b, value: 2}, {op: remove, path: /a/missing/c}]",
+                "Patch operation 1 failed: path `/a/missing` does not exist",
+            ),
+            (
+                "[{op: replace, path: /list/1, value: y}]",
+                "Patch operation 0 failed: invalid index at `/list/1` for a sequence of length 1",
+            ),
+            (
+                "[{op: add, path: /a/b/c, value: 1}]",
+                "Patch operation 0 failed: `/a/b` is neither a mapping nor a sequence",
+            ),
+            (
+                "[{op: move, from: /a, path: /a/b}]",
+                "Patch operation 0 failed: cannot move `/a` into its own child `/a/b`",
+            ),
+            (
+                "[{op: remove, path: a}]",
+                "Patch operation 0 failed: invalid JSON Pointer `a`",
+            ),
+        ];
+        for (patch, message) in cases {
+            let err = apply_patch(&document, &ops(patch)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn diff_applies_to_its_left_side() {
+        let left = yaml("a: 1\nlist: [a, b, c]\nnested: {x: [1, 2]}\n");
+        let right = yaml("a: 2\nlist: [c, a, d]\nnested: {x: [2], y: true}\n");
+        assert_eq!(apply_diff(&left, &diff(&left, &right)).unwrap(), right);
+
+        let (left, right) = (yaml("old"), yaml("[new]"));
+        assert_eq!(apply_diff(&left, &diff(&left, &right)).unwrap(), right);
+    }
+
+    #[test]
+    fn diff_ported_to_a_drifted_document() {
+        let staging = yaml("replicas: 2\nimage: app:1\n");
+        let staging_next = yaml("replicas: 2\nimage: app:2\n");
+        let changes = diff(&staging, &staging_next);
+
+        let production = yaml("replicas: 5\nimage: app:1\n");
+        assert_eq!(
+            apply_diff(&production, &changes).unwrap(),
+            yaml("replicas: 5\nimage: app:2\n")
+        );
+
+        let drifted = yaml("replicas: 5\nimage: app:0\n");
+        let err = apply_diff(&drifted, &changes).unwrap_err();
+        assert!(
+            matches!(err.kind, PatchErrorKind::TestFailed { .. }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn diff_guards_compare_tags() {
+        let left = yaml("bucket: !Ref Name\nsize: 1\n");
+        let changes = diff(&yaml("bucket: !Ref Name\n"), &yaml("bucket: !Ref Other\n"));
+        assert_eq!(
+            apply_diff(&left, &changes).unwrap(),
+            yaml("bucket: !Ref Other\nsize: 1\n")
+        );
+
+        let retagged = yaml("bucket: !Sub Name\nsize: 2\n");
+        let err = apply_diff(&retagged, &changes).unwrap_err();
+        assert!(
+            matches!(err.kind, PatchErrorKind::TestFailed { .. }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn diff_round_trips_through_merge_keys_when_raw() {
+        let left = yaml("x: {<<: {a: 1}, b: 2}\n");
+        let right = yaml("x: {<<: {a: 3}, b: 2}\n");
+
+        let expanded = crate::diff_values(&left, &right, &DiffOptions::default()).unwrap();
+        let err = apply_diff(&left, &expanded).unwrap_err();
+        assert_eq!(
+            err.kind,
+            PatchErrorKind::ExpandedMergeKey("/x/a".to_string())
+        );
+
+        let raw = DiffOptions {
+            raw_merge_keys: true,
+            ..Default::default()
+        };
+        let diffs = crate::diff_values(&left, &right, &raw).unwrap();
+        assert_eq!(apply_diff(&left, &diffs).unwrap(), right);
+
+        // Members written next to the merge key apply either way.
+        let right = yaml("x: {<<: {a: 1}, b: 4}\n");
+        let diffs = crate::diff_values(&left, &right, &DiffOptions::default()).unwrap();
+        assert_eq!(apply_diff(&left, &diffs).unwrap(), right);
+    }
+
+    #[test]
+    fn multi_document_diff_is_rejected() {
+        let (one, two) = (
+            crate::read_yaml("a: 1\n---\nb: 1\n").unwrap(),
+            crate::read_yaml("a: 2\n---\nb: 2\n").unwrap(),
+        );
+        let diffs = crate::diff_documents(&one, &two, &DiffOptions::default()).unwrap();
+        let err = apply_diff(&one[0], &diffs).unwrap_err();
+        assert_eq!(err.kind, PatchErrorKind::MultiDocumentDiff);
+        assert_eq!(
+            err.to_string(),
+            "Cannot apply a diff of several documents to a single document"
+        );
+    }
+
+    #[test]
+    fn serialized_diff_is_recognised() {
+        let patch = Patch::from_value(yaml(
+            "- key: a\n  has_diff: true\n  diff_type: modified\n  diff: {left_value: 1, right_value: 2}\n  children:\n    - key: null\n      has_diff: true\n      diff_type: modified\n      diff: {left_value: 1, right_value: 2}\n",
+        ))
+        .unwrap();
+        assert!(matches!(patch, Patch::Diff(_)));
+        assert_eq!(patch.apply(&yaml("a: 1")).unwrap(), yaml("a: 2"));
+
+        assert!(matches!(
+            Patch::from_value(yaml("[{op: remove, path: /a}]")).unwrap(),
+            Patch::Operations(_)
+        ));
+        assert_eq!(
+            Patch::from_value(yaml("[{op: delete, path: /a}]"))
+                .err()
+                .unwrap()
+                .split(':')
+                .next(),
+            Some("invalid JSON Patch")
+        );
+    }
+}
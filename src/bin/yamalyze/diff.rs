@@ -0,0 +1,71 @@
+//! `yamalyze diff LEFT RIGHT`: print the structural diff of two YAML files
+//! as a tree, or with `--format yaml` as a `DiffReport` other tools can
+//! load, with the source span of each value. Exit codes follow `diff(1)`:
+//! 0 when the documents are equal, 1 when they differ and 2 on errors.
+
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::ExitCode;
+
+use yamalyze::source::attach_spans;
+use yamalyze::{diff_documents, DiffReport};
+
+use crate::render::Renderer;
+use crate::{parse_args, parse_documents, read_text};
+
+pub fn run(args: &[String]) -> Result<ExitCode, String> {
+    let args = parse_args(args, &["--color", "--format"])?;
+    let [left, right] = args.positional.as_slice() else {
+        return Err("diff needs the LEFT and RIGHT files".to_string());
+    };
+    if left == "-" && right == "-" {
+        return Err("only one side can be read from stdin".to_string());
+    }
+    let renderer = Renderer {
+        color: use_color(args.flags.get("--color").map(String::as_str))?,
+    };
+
+    let (left_text, right_text) = (read_text(left)?, read_text(right)?);
+    let mut diffs = diff_documents(
+        &parse_documents(left, &left_text)?,
+        &parse_documents(right, &right_text)?,
+        &args.options,
+    )
+    .map_err(|e| e.to_string())?;
+    let changed = diffs.iter().any(|d| d.has_diff);
+    match args.flags.get("--format").map_or("tree", String::as_str) {
+        "tree" if changed => print(&renderer.tree(&diffs))?,
+        "tree" => {}
+        "yaml" => {
+            attach_spans(&mut diffs, &left_text, &right_text, &args.options);
+            print(&serde_yml::to_string(&DiffReport::new(diffs)).map_err(|e| e.to_string())?)?
+        }
+        other => return Err(format!("--format `{other}` is not one of tree or yaml")),
+    }
+    Ok(if changed {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Whether to colour output for `--color WHEN`: `always`, `never` or
+/// `auto`, the default, which colours a terminal unless `NO_COLOR` is set.
+pub fn use_color(when: Option<&str>) -> Result<bool, String> {
+    match when.unwrap_or("auto") {
+        "always" => Ok(true),
+        "never" => Ok(false),
+        "auto" => Ok(io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none()),
+        other => Err(format!(
+            "--color `{other}` is not one of auto, always or never"
+        )),
+    }
+}
+
+/// Write to stdout, treating a closed pipe (`| head`) as success.
+pub fn print(text: &str) -> Result<(), String> {
+    match io::stdout().lock().write_all(text.as_bytes()) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(format!("stdout: {e}")),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,91 @@
+//! `yamalyze git-diff`, for `GIT_EXTERNAL_DIFF` or `diff.<driver>.command`:
+//! git passes `path old-file old-hex old-mode new-file new-hex new-mode`,
+//! plus the new path and a rename message for renames, with `/dev/null`
+//! standing in for the side of a created or deleted file.
+//!
+//! ```text
+//! # .gitattributes
+//! *.yaml diff=yamalyze
+//! # .git/config
+//! [diff "yamalyze"]
+//!     command = yamalyze git-diff
+//! ```
+//!
+//! Git stops at the first external diff that exits non-zero, so this exits
+//! 0 whether or not the file changed, and reports a file it can't read or
+//! parse under its header before moving on.
+
+use std::env;
+use std::process::ExitCode;
+
+use yamalyze::diff_documents;
+use yamalyze::options::DiffOptions;
+
+use crate::diff::{print, use_color};
+use crate::read_documents;
+use crate::render::Renderer;
+
+const NULL_FILE: &str = "/dev/null";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+pub fn run(args: &[String]) -> Result<ExitCode, String> {
+    let args = crate::parse_args(args, &["--color"])?;
+    let (path, old_file, new_file, new_path) = match args.positional.as_slice() {
+        [path, old_file, _, _, new_file, _, _] => (path, old_file, new_file, path),
+        [path, old_file, _, _, new_file, _, _, new_path, _] => (path, old_file, new_file, new_path),
+        _ => return Err("git-diff takes the 7 or 9 arguments git passes".to_string()),
+    };
+    let when = args.flags.get("--color").map(String::as_str);
+    // Git pipes external diff output into its pager, so `auto` also
+    // colours when git says the pager is showing it on a terminal.
+    let color = use_color(when)?
+        || (when.unwrap_or("auto") == "auto" && env::var_os("GIT_PAGER_IN_USE").is_some());
+    let renderer = Renderer { color };
+
+    let old_name = side_name("a", path, old_file);
+    let new_name = side_name("b", new_path, new_file);
+    let body = match file_diff(old_file, new_file, &args.options) {
+        Ok(None) if path == new_path => return Ok(ExitCode::SUCCESS),
+        Ok(None) => String::new(),
+        Ok(Some(diffs)) => renderer.tree(&diffs),
+        Err(message) => format!("yamalyze: {message}\n"),
+    };
+    let header = format!("--- {old_name}\n+++ {new_name}\n");
+    let header = if color {
+        format!("{BOLD}{header}{RESET}")
+    } else {
+        header
+    };
+    print(&format!("{header}{body}"))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `a/path`, or `/dev/null` for the missing side of a created or deleted
+/// file.
+fn side_name(prefix: &str, path: &str, file: &str) -> String {
+    if file == NULL_FILE {
+        NULL_FILE.to_string()
+    } else {
+        format!("{prefix}/{path}")
+    }
+}
+
+/// The diff of the two files, `None` when their documents are equal. A
+/// `/dev/null` side is an empty stream, so the other side shows up whole.
+fn file_diff(
+    old_file: &str,
+    new_file: &str,
+    options: &DiffOptions,
+) -> Result<Option<Vec<yamalyze::YamlDiff>>, String> {
+    let read = |file: &str| {
+        if file == NULL_FILE {
+            Ok(Vec::new())
+        } else {
+            read_documents(file)
+        }
+    };
+    let diffs =
+        diff_documents(&read(old_file)?, &read(new_file)?, options).map_err(|e| e.to_string())?;
+    Ok(diffs.iter().any(|d| d.has_diff).then_some(diffs))
+}
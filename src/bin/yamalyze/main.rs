@@ -0,0 +1,195 @@
+//! Native command line for the yamalyze engine.
+
+mod diff;
+mod git_diff;
+mod merge;
+mod render;
+
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::process::ExitCode;
+use std::{env, fs};
+
+use yamalyze::options::{DiffOptions, SequenceKey};
+
+const USAGE: &str = "\
+Usage:
+  yamalyze diff [OPTIONS] LEFT RIGHT
+  yamalyze git-diff [OPTIONS] PATH OLD-FILE OLD-HEX OLD-MODE NEW-FILE NEW-HEX NEW-MODE
+  yamalyze merge [OPTIONS] BASE OURS THEIRS
+
+Commands:
+  diff     Print the structural diff of two YAML files (`-` reads stdin) as
+           a tree; exits 0 if they are equal, 1 if they differ, 2 on error
+  git-diff GIT_EXTERNAL_DIFF / diff.<driver>.command: print one file's
+           structural diff under a header; `/dev/null` marks a created or
+           deleted file
+  merge    Three-way merge of YAML files, as a git merge driver: writes the
+           result to OURS and exits 1 if conflicts were marked in it; OURS
+           is left as is when their changes are already in it, otherwise
+           rewriting it drops its comments and formatting
+
+Options:
+  --key PATH=FIELD[,FIELD...]  Match elements of the sequences at PATH by
+                               the given fields, e.g. spec.containers=name
+  --options FILE               Read diff options from a YAML or JSON file
+  --format FORMAT              Output `tree`, the default, or a `yaml`
+                               diff report (diff)
+  --color WHEN                 Colour output: auto, always or never (diff, git-diff)
+  --marker-size N              Length of conflict markers (merge, default 7)
+  --name PATH                  Path to name in conflict messages (merge)
+";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("diff") => diff::run(&args[1..]),
+        Some("git-diff") => git_diff::run(&args[1..]),
+        Some("merge") => merge::run(&args[1..]),
+        Some("-h" | "--help") => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Some(other) => Err(format!("unknown command `{other}`\n\n{USAGE}")),
+        None => Err(format!("missing command\n\n{USAGE}")),
+    };
+    match result {
+        Ok(code) => code,
+        Err(message) => {
+            eprintln!("yamalyze: {message}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// A command's arguments once the shared flags are read.
+struct Args {
+    options: DiffOptions,
+    positional: Vec<String>,
+    /// Values of the command-specific flags that were given.
+    flags: HashMap<String, String>,
+}
+
+/// Read `--key` and `--options`, plus the value-taking flags in
+/// `command_flags`. Flags take their value as the next argument or after
+/// `=`; `--` ends the flags.
+fn parse_args(args: &[String], command_flags: &[&str]) -> Result<Args, String> {
+    let mut options_file = None;
+    let mut keys = Vec::new();
+    let mut positional = Vec::new();
+    let mut flags = HashMap::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.by_ref().cloned());
+            break;
+        }
+        if !arg.starts_with("--") {
+            positional.push(arg.clone());
+            continue;
+        }
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, value.to_string()),
+            None => {
+                let value = args.next().ok_or_else(|| format!("{arg} needs a value"))?;
+                (arg.as_str(), value.clone())
+            }
+        };
+        match flag {
+            "--options" => options_file = Some(value),
+            "--key" => keys.push(parse_key(&value)?),
+            _ if command_flags.contains(&flag) => {
+                flags.insert(flag.to_string(), value);
+            }
+            _ => return Err(format!("unknown option `{flag}`")),
+        }
+    }
+
+    let mut options = match options_file {
+        Some(path) => {
+            let text = fs::read_to_string(&path).map_err(|e| format!("{path}: {e}"))?;
+            serde_yml::from_str(&text).map_err(|e| format!("{path}: invalid options: {e}"))?
+        }
+        None => DiffOptions::default(),
+    };
+    // Keys from the command line win over those from the options file.
+    options.sequence_keys.splice(0..0, keys);
+    options.validate().map_err(|e| e.to_string())?;
+
+    Ok(Args {
+        options,
+        positional,
+        flags,
+    })
+}
+
+/// `PATH=FIELD[,FIELD...]`.
+fn parse_key(spec: &str) -> Result<SequenceKey, String> {
+    let (path, fields) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("--key `{spec}` is not PATH=FIELD[,FIELD...]"))?;
+    let fields: Vec<&str> = fields.split(',').map(str::trim).collect();
+    Ok(SequenceKey::new(path, &fields))
+}
+
+/// The text of the file at `path`, or of stdin for `-`.
+fn read_text(path: &str) -> Result<String, String> {
+    if path == "-" {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|e| format!("stdin: {e}"))?;
+        Ok(text)
+    } else {
+        fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))
+    }
+}
+
+/// Every document of the YAML text read from `path`.
+fn parse_documents(path: &str, text: &str) -> Result<Vec<serde_yml::Value>, String> {
+    yamalyze::read_yaml(text).map_err(|e| format!("{path}: {e}"))
+}
+
+/// Every document of the YAML file at `path`, or of stdin for `-`.
+fn read_documents(path: &str) -> Result<Vec<serde_yml::Value>, String> {
+    parse_documents(path, &read_text(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn flags_and_positionals() {
+        let parsed = parse_args(
+            &args(&[
+                "--key",
+                "spec.containers=name",
+                "a.yaml",
+                "--marker-size=9",
+                "--",
+                "--b.yaml",
+            ]),
+            &["--marker-size"],
+        )
+        .unwrap();
+        assert_eq!(parsed.positional, args(&["a.yaml", "--b.yaml"]));
+        assert_eq!(parsed.flags["--marker-size"], "9");
+        assert_eq!(parsed.options.sequence_keys[0].fields, args(&["name"]));
+    }
+
+    #[test]
+    fn bad_flags_are_errors() {
+        assert!(parse_args(&args(&["--marker-size", "9"]), &[]).is_err());
+        assert!(parse_args(&args(&["--key"]), &[]).is_err());
+        assert_eq!(
+            parse_args(&args(&["--key", "items=,"]), &[]).err().unwrap(),
+            "Invalid options: sequence_keys[0].fields must not contain empty names"
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! `yamalyze merge BASE OURS THEIRS`, git's merge driver contract: the
+//! three paths are `%O %A %B`, the result replaces OURS, and the exit code
+//! is 1 when conflicts were left in it. OURS is only rewritten when the
+//! merge changes it, since writing it out drops comments, anchors and
+//! formatting. Register it with
+//!
+//! ```text
+//! # .gitattributes
+//! *.yaml merge=yamalyze
+//! # .git/config
+//! [merge "yamalyze"]
+//!     driver = yamalyze merge --marker-size %L --name %P %O %A %B
+//! ```
+
+use std::fs;
+use std::process::ExitCode;
+
+use yamalyze::merge::three_way_merge;
+use yamalyze::path::json_pointer;
+
+use crate::{parse_args, read_documents};
+
+pub fn run(args: &[String]) -> Result<ExitCode, String> {
+    let args = parse_args(args, &["--marker-size", "--name"])?;
+    let [base, ours, theirs] = args.positional.as_slice() else {
+        return Err("merge needs the BASE, OURS and THEIRS files".to_string());
+    };
+    let marker_size = match args.flags.get("--marker-size") {
+        Some(size) => size
+            .parse()
+            .map_err(|_| format!("--marker-size `{size}` is not a number"))?,
+        None => 7,
+    };
+    // Git hands the driver temporary files; `--name` is the real path.
+    let name = args.flags.get("--name").unwrap_or(ours);
+
+    let base_documents = read_documents(base)?;
+    let ours_documents = read_documents(ours)?;
+    let theirs_documents = read_documents(theirs)?;
+    if base_documents.len() != ours_documents.len()
+        || base_documents.len() != theirs_documents.len()
+    {
+        return Err(format!(
+            "{name}: the three versions have different numbers of documents"
+        ));
+    }
+
+    let mut merged = String::new();
+    let mut conflicted = false;
+    let mut ours_unchanged = true;
+    let documents = base_documents
+        .iter()
+        .zip(&ours_documents)
+        .zip(&theirs_documents);
+    for (i, ((base, ours_document), theirs)) in documents.enumerate() {
+        let result = three_way_merge(base, ours_document, theirs, &args.options)
+            .map_err(|e| format!("{name}: {e}"))?;
+        for conflict in &result.conflicts {
+            let pointer = json_pointer(&conflict.path);
+            let at = if pointer.is_empty() { "/" } else { &pointer };
+            eprintln!("CONFLICT ({}): {name} at {at}", conflict.kind);
+        }
+        conflicted |= !result.conflicts.is_empty();
+        ours_unchanged &= result.conflicts.is_empty() && result.merged == *ours_document;
+        if i > 0 {
+            merged.push_str("---\n");
+        }
+        merged.push_str(
+            &result
+                .to_yaml_with_markers(marker_size)
+                .map_err(|e| format!("{name}: {e}"))?,
+        );
+    }
+
+    // Rewriting OURS loses its comments and formatting, so only do it when
+    // the merge changed something.
+    if !ours_unchanged {
+        fs::write(ours, merged).map_err(|e| format!("{ours}: {e}"))?;
+    }
+    Ok(if conflicted {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
@@ -0,0 +1,332 @@
+//! Diff trees as indented terminal text, one node per line, the way the
+//! web page lays them out: a sigil column (`+` added, `-` deleted, `~`
+//! modified, `>` moved), then the key and value. Unchanged nodes are left
+//! out.
+
+use std::ops::Range;
+
+use yamalyze::{DiffType, LineChange, LineHunk, YamlDiff};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+const REVERSE: &str = "\x1b[7m";
+const NO_REVERSE: &str = "\x1b[27m";
+
+/// Unchanged lines shown next to each change in a multi-line string.
+const CONTEXT_LINES: usize = 3;
+
+pub struct Renderer {
+    /// Colour with ANSI escapes.
+    pub color: bool,
+}
+
+impl Renderer {
+    pub fn tree(&self, diffs: &[YamlDiff]) -> String {
+        let mut out = String::new();
+        for node in diffs {
+            self.node(&mut out, node, 0);
+        }
+        out
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.color {
+            format!("{color}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn node(&self, out: &mut String, node: &YamlDiff, depth: usize) {
+        if !node.has_diff {
+            return;
+        }
+        let label = match &node.key {
+            Some(key) => format!("{key}: "),
+            None => String::new(),
+        };
+        // A scalar under a key arrives wrapped in one bare child; show it
+        // on the key's line.
+        let leaf = match node.children.as_slice() {
+            [] => Some(node),
+            [child] if child.children.is_empty() && child.key.is_none() => Some(child),
+            _ => None,
+        };
+        let mut notes = String::new();
+        if let Some(match_key) = &node.match_key {
+            notes.push_str(&format!(" (matched by {match_key})"));
+        }
+        if node.replaced {
+            notes.push_str(" (replacement)");
+        }
+        if node.tag_changed && leaf.is_none() {
+            let tag = |tag: &Option<String>| tag.clone().unwrap_or_else(|| "no tag".to_string());
+            notes.push_str(&format!(
+                " (tag {} → {})",
+                tag(&node.diff.left_tag),
+                tag(&node.diff.right_tag)
+            ));
+        }
+        if let (DiffType::Moved, Some(from), Some(to)) =
+            (&node.diff_type, node.left_index, node.right_index)
+        {
+            notes.push_str(&format!(" (moved {from} → {to})"));
+        }
+        if !notes.is_empty() {
+            notes = self.paint(DIM, &notes);
+        }
+        let kind = match (&node.diff_type, leaf) {
+            (DiffType::Moved, _) | (_, None) => &node.diff_type,
+            (_, Some(leaf)) => &leaf.diff_type,
+        };
+        let (sigil, color) = match kind {
+            DiffType::Additions => ("+", GREEN),
+            DiffType::Deletions => ("-", RED),
+            DiffType::Modified => ("~", YELLOW),
+            DiffType::Moved => (">", CYAN),
+            DiffType::Unchanged => (" ", DIM),
+        };
+        let indent = "  ".repeat(depth);
+        let sigil = self.paint(color, sigil);
+
+        let Some(leaf) = leaf else {
+            let label = label.trim_end();
+            out.push_str(&format!("{sigil} {indent}{label}{notes}\n"));
+            for child in &node.children {
+                self.node(out, child, depth + 1);
+            }
+            return;
+        };
+        if let Some(hunks) = &leaf.line_hunks {
+            let label = label.trim_end();
+            out.push_str(&format!("{sigil} {indent}{label}{notes}\n"));
+            self.text_lines(out, leaf, hunks, depth + 1);
+            return;
+        }
+        let left = with_tag(&leaf.diff.left_tag, format_value(&leaf.diff.left_value));
+        let right = with_tag(&leaf.diff.right_tag, format_value(&leaf.diff.right_value));
+        let value = match leaf.diff_type {
+            DiffType::Additions => self.paint(GREEN, &right),
+            DiffType::Deletions => self.paint(RED, &left),
+            DiffType::Modified => match (
+                &leaf.inline_changes,
+                leaf.diff.left_value.as_str(),
+                leaf.diff.right_value.as_str(),
+            ) {
+                (Some(changes), Some(left), Some(right)) if self.color => format!(
+                    "{} → {}",
+                    highlight(RED, &leaf.diff.left_tag, left, &changes.left),
+                    highlight(GREEN, &leaf.diff.right_tag, right, &changes.right)
+                ),
+                _ => format!("{} → {}", self.paint(RED, &left), self.paint(GREEN, &right)),
+            },
+            DiffType::Moved | DiffType::Unchanged => right,
+        };
+        out.push_str(&format!("{sigil} {indent}{label}{value}{notes}\n"));
+    }
+
+    /// A changed multi-line string as a unified diff of its lines, with
+    /// long unchanged runs cut down to `CONTEXT_LINES` around changes.
+    fn text_lines(&self, out: &mut String, leaf: &YamlDiff, hunks: &[LineHunk], depth: usize) {
+        let left: Vec<&str> = leaf
+            .diff
+            .left_value
+            .as_str()
+            .unwrap_or_default()
+            .lines()
+            .collect();
+        let right: Vec<&str> = leaf
+            .diff
+            .right_value
+            .as_str()
+            .unwrap_or_default()
+            .lines()
+            .collect();
+        let indent = "  ".repeat(depth);
+        let last = hunks.len().saturating_sub(1);
+        for (i, hunk) in hunks.iter().enumerate() {
+            let (sigil, color, lines) = match hunk.change {
+                LineChange::Delete => ("-", RED, &left[hunk.left_lines.clone()]),
+                LineChange::Insert => ("+", GREEN, &right[hunk.right_lines.clone()]),
+                LineChange::Equal => {
+                    let lines = &left[hunk.left_lines.clone()];
+                    let head = if i == 0 {
+                        0
+                    } else {
+                        CONTEXT_LINES.min(lines.len())
+                    };
+                    let tail = if i == last {
+                        0
+                    } else {
+                        CONTEXT_LINES.min(lines.len() - head)
+                    };
+                    for line in &lines[..head] {
+                        out.push_str(&format!("  {indent}{line}\n"));
+                    }
+                    let skipped = lines.len() - head - tail;
+                    if skipped > 0 {
+                        let plural = if skipped == 1 { "" } else { "s" };
+                        let note = format!("… {skipped} unchanged line{plural}");
+                        out.push_str(&format!("  {indent}{}\n", self.paint(DIM, &note)));
+                    }
+                    for line in &lines[lines.len() - tail..] {
+                        out.push_str(&format!("  {indent}{line}\n"));
+                    }
+                    continue;
+                }
+            };
+            for line in lines {
+                let sigil = self.paint(color, sigil);
+                out.push_str(&format!("{sigil} {indent}{}\n", self.paint(color, line)));
+            }
+        }
+    }
+}
+
+/// A string quoted as by `format_value` and painted in `color`, with the
+/// chars in `changed` shown in reverse video.
+fn highlight(color: &str, tag: &Option<String>, value: &str, changed: &[Range<usize>]) -> String {
+    let mut out = format!("{color}{}", with_tag(tag, String::new()));
+    out.push('"');
+    let mut reversed = false;
+    for (i, c) in value.chars().enumerate() {
+        let inside = changed.iter().any(|range| range.contains(&i));
+        if inside != reversed {
+            out.push_str(if inside { REVERSE } else { NO_REVERSE });
+            reversed = inside;
+        }
+        out.extend(c.escape_debug());
+    }
+    if reversed {
+        out.push_str(NO_REVERSE);
+    }
+    out.push('"');
+    out.push_str(RESET);
+    out
+}
+
+/// A leaf value preceded by its tag, if any, as it would be written.
+fn with_tag(tag: &Option<String>, value: String) -> String {
+    match tag {
+        Some(tag) => format!("{tag} {value}"),
+        None => value,
+    }
+}
+
+/// A leaf value on one line: strings quoted, collections in flow style.
+fn format_value(value: &serde_yml::Value) -> String {
+    match value {
+        serde_yml::Value::Null => "null".to_string(),
+        serde_yml::Value::String(s) => format!("{s:?}"),
+        serde_yml::Value::Bool(b) => b.to_string(),
+        serde_yml::Value::Number(n) => n.to_string(),
+        serde_yml::Value::Tagged(tagged) => {
+            format!("{} {}", tagged.tag, format_value(&tagged.value))
+        }
+        serde_yml::Value::Sequence(seq) => {
+            let items: Vec<String> = seq.iter().map(format_value).collect();
+            format!("[{}]", items.join(", "))
+        }
+        serde_yml::Value::Mapping(map) => {
+            let members: Vec<String> = map
+                .iter()
+                .map(|(k, v)| format!("{}: {}", format_value(k), format_value(v)))
+                .collect();
+            format!("{{{}}}", members.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yamalyze::options::DiffOptions;
+
+    fn render(left: &str, right: &str) -> String {
+        let left = yamalyze::read_yaml(left).unwrap();
+        let right = yamalyze::read_yaml(right).unwrap();
+        let diffs = yamalyze::diff_documents(&left, &right, &DiffOptions::default()).unwrap();
+        Renderer { color: false }.tree(&diffs)
+    }
+
+    #[test]
+    fn changed_nodes_as_a_tree() {
+        assert_eq!(
+            render(
+                "name: a\nspec:\n  replicas: 1\n  paused: false\nold: [1]\n",
+                "name: a\nspec:\n  replicas: 2\n  paused: false\n  image: nginx\n",
+            ),
+            "~ spec:\n\
+             ~   replicas: 1 → 2\n\
+             +   image: \"nginx\"\n\
+             - old:\n\
+             -   0: 1\n"
+        );
+    }
+
+    #[test]
+    fn keyed_and_moved_elements() {
+        assert_eq!(
+            render(
+                "- {name: a, v: 1}\n- {name: b, v: 1}\n",
+                "- {name: b, v: 2}\n- {name: a, v: 1}\n",
+            ),
+            "> 0: (moved 1 → 0)\n\
+             ~   v: 1 → 2\n"
+        );
+    }
+
+    #[test]
+    fn multi_line_strings_as_unified_diffs() {
+        assert_eq!(
+            render(
+                "run: |\n  a\n  b\n  c\n  d\n  e\n  f\n  g\n  h\n",
+                "run: |\n  a\n  b\n  c\n  d\n  e\n  f\n  G\n  h\n",
+            ),
+            [
+                "~ run:",
+                "    … 3 unchanged lines",
+                "    d",
+                "    e",
+                "    f",
+                "-   g",
+                "+   G",
+                "    h\n",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn changed_characters_are_highlighted() {
+        let left = yamalyze::read_yaml("image: nginx:1.25\n").unwrap();
+        let right = yamalyze::read_yaml("image: nginx:1.26\n").unwrap();
+        let options = DiffOptions {
+            inline_granularity: yamalyze::options::InlineGranularity::Char,
+            ..Default::default()
+        };
+        let diffs = yamalyze::diff_documents(&left, &right, &options).unwrap();
+        assert_eq!(
+            Renderer { color: true }.tree(&diffs),
+            "\x1b[33m~\x1b[0m image: \
+             \x1b[31m\"nginx:1.2\x1b[7m5\x1b[27m\"\x1b[0m → \
+             \x1b[32m\"nginx:1.2\x1b[7m6\x1b[27m\"\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn tags_are_shown_with_values() {
+        assert_eq!(
+            render(
+                "a: !Ref Foo\nb: !If [c, x]\n",
+                "a: !Sub Foo\nb: !Or [c, x]\n",
+            ),
+            "~ a: !Ref \"Foo\" → !Sub \"Foo\"\n\
+             ~ b: (tag !If → !Or)\n"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use crate::diff::{unwrap_tagged, unwrap_tagged_mut, YamlDiff};
+use crate::path::PathSegment;
+
+/// The long-form key of a short-form intrinsic function tag, e.g.
+/// `Fn::Join` for `!Join`. `None` for tags that are not intrinsics.
+fn long_form(tag: &str) -> Option<String> {
+    let name = tag.strip_prefix('!')?;
+    match name {
+        "Ref" | "Condition" => Some(name.to_string()),
+        "And" | "Base64" | "Cidr" | "Equals" | "FindInMap" | "GetAZs" | "GetAtt" | "If"
+        | "ImportValue" | "Join" | "Length" | "Not" | "Or" | "Select" | "Split" | "Sub"
+        | "ToJsonString" | "Transform" => Some(format!("Fn::{name}")),
+        _ => None,
+    }
+}
+
+/// Rewrite short-form intrinsic functions into their long form, so that
+/// `!Ref X` becomes `{Ref: X}` and `!GetAtt A.B` becomes
+/// `{Fn::GetAtt: [A, B]}`, the way CloudFormation reads them. Other tags
+/// are left alone.
+pub(crate) fn normalize_intrinsics(value: &mut serde_yml::Value) {
+    if let serde_yml::Value::Tagged(tagged) = value {
+        if let Some(key) = long_form(&tagged.tag.to_string()) {
+            let mut argument = std::mem::replace(&mut tagged.value, serde_yml::Value::Null);
+            normalize_intrinsics(&mut argument);
+            if key == "Fn::GetAtt" {
+                if let Some((resource, attribute)) =
+                    argument.as_str().and_then(|s| s.split_once('.'))
+                {
+                    argument = serde_yml::Value::Sequence(vec![resource.into(), attribute.into()]);
+                }
+            }
+            let mut function = serde_yml::Mapping::new();
+            function.insert(key.into(), argument);
+            *value = serde_yml::Value::Mapping(function);
+            return;
+        }
+    }
+    match unwrap_tagged_mut(value) {
+        serde_yml::Value::Mapping(map) => map.values_mut().for_each(normalize_intrinsics),
+        serde_yml::Value::Sequence(seq) => seq.iter_mut().for_each(normalize_intrinsics),
+        _ => {}
+    }
+}
+
+/// Annotate the diff of two templates: resources whose `Type` changed are
+/// marked `replaced`.
+pub(crate) fn annotate_templates(diffs: &mut [YamlDiff]) {
+    for node in diffs {
+        match node.path.as_slice() {
+            [PathSegment::Key(section), PathSegment::Key(_)] if section == "Resources" => {
+                node.replaced = type_changed(&node.diff.left_value, &node.diff.right_value);
+            }
+            [] | [_] => annotate_templates(&mut node.children),
+            _ => {}
+        }
+    }
+}
+
+/// Whether both resources declare a `Type` and the two differ.
+fn type_changed(left: &serde_yml::Value, right: &serde_yml::Value) -> bool {
+    let resource_type =
+        |resource: &serde_yml::Value| resource.get("Type").map(unwrap_tagged).cloned();
+    match (resource_type(left), resource_type(right)) {
+        (Some(left), Some(right)) => left != right,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::DiffType;
+    use crate::options::DiffOptions;
+
+    fn normalized(yaml: &str) -> String {
+        let mut value: serde_yml::Value = serde_yml::from_str(yaml).unwrap();
+        normalize_intrinsics(&mut value);
+        serde_yml::to_string(&value).unwrap()
+    }
+
+    fn template_diff(left: &str, right: &str) -> Vec<YamlDiff> {
+        let options = DiffOptions {
+            cloudformation: true,
+            ..Default::default()
+        };
+        let (left, right) = (
+            crate::read_yaml(left).unwrap(),
+            crate::read_yaml(right).unwrap(),
+        );
+        crate::diff_documents(&left, &right, &options).unwrap()
+    }
+
+    #[test]
+    fn short_forms_become_long_forms() {
+        assert_eq!(normalized("!Ref Bucket"), "Ref: Bucket\n");
+        assert_eq!(
+            normalized("!GetAtt Db.Endpoint.Address"),
+            "Fn::GetAtt:\n- Db\n- Endpoint.Address\n"
+        );
+        assert_eq!(
+            normalized("!If [Prod, !Sub '${A}', !Ref AWS::NoValue]"),
+            "Fn::If:\n- Prod\n- Fn::Sub: ${A}\n- Ref: AWS::NoValue\n"
+        );
+        assert_eq!(normalized("!Custom x"), "!Custom x\n");
+    }
+
+    #[test]
+    fn equivalent_templates_compare_equal() {
+        let diffs = template_diff(
+            "Outputs:\n  Arn:\n    Value: !GetAtt Bucket.Arn\n  Name:\n    Value: !Join ['-', [a, !Ref B]]\n",
+            "Outputs:\n  Arn:\n    Value:\n      Fn::GetAtt: [Bucket, Arn]\n  Name:\n    Value:\n      Fn::Join: ['-', [a, {Ref: B}]]\n",
+        );
+        assert!(diffs.iter().all(|d| !d.has_diff));
+        assert_eq!(diffs[0].match_key, None);
+    }
+
+    #[test]
+    fn resource_type_changes_are_replacements() {
+        let diffs = template_diff(
+            "Resources:\n  Queue:\n    Type: AWS::SQS::Queue\n  Topic:\n    Type: AWS::SNS::Topic\n",
+            "Resources:\n  Queue:\n    Type: AWS::SNS::Topic\n  Topic:\n    Type: AWS::SNS::Topic\n    Properties: {}\n",
+        );
+        let resources = &diffs[0].children;
+        assert_eq!(resources[0].diff_type, DiffType::Modified);
+        assert!(resources[0].replaced);
+        assert_eq!(resources[1].diff_type, DiffType::Modified);
+        assert!(!resources[1].replaced);
+    }
+}
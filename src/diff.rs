@@ -1,50 +1,195 @@
-use wasm_bindgen::prelude::*;
+use std::collections::{HashMap, HashSet, VecDeque};
 
-const MAX_DEPTH: usize = 256;
+use serde::{de, Deserialize, Deserializer, Serialize};
 
-/// When two sequences have a product of lengths exceeding this limit,
-/// fall back to positional comparison instead of Myers diff to avoid
-/// excessive memory/time on pathological inputs.
-const SEQ_DIFF_PRODUCT_LIMIT: usize = 10_000_000;
+use crate::error::DiffError;
+use crate::merge_keys::prepare_documents;
+use crate::options::{DiffOptions, SequenceIndex};
+use crate::path::{dotted_path, json_pointer, PathSegment};
+use crate::source::Span;
+use crate::text::{inline_changes, is_multi_line, line_hunks, InlineChanges, LineHunk};
 
-#[derive(Clone, Debug, PartialEq)]
-#[cfg_attr(test, derive(serde::Serialize))]
-pub(crate) enum DiffType {
+/// Fields tried, in order, when looking for an identity key shared by every
+/// element of two sequences of mappings.
+const AUTO_KEY_CANDIDATES: [&str; 3] = ["name", "id", "key"];
+
+/// Minimum line-level similarity for a removed and an inserted element to
+/// be reported as one element that moved and changed.
+const MOVE_SIMILARITY_THRESHOLD: f32 = 0.6;
+
+/// Skip the similarity search for moves when it would compare more
+/// removed/inserted element pairs than this.
+const MOVE_SEARCH_LIMIT: usize = 10_000;
+
+/// How a node differs between the left and right document. Serialized as
+/// its lowercase name, e.g. `"modified"`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffType {
     Unchanged,
     Additions,
     Deletions,
     Modified,
+    Moved,
 }
 
-#[derive(Clone, Debug)]
-#[cfg_attr(test, derive(serde::Serialize))]
-pub(crate) struct DiffValue {
-    pub(crate) left_value: serde_yml::Value,
-    pub(crate) right_value: serde_yml::Value,
+/// A node's value in each document, `Null` where it is absent. Values are
+/// kept without their YAML tag, which is recorded next to them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DiffValue {
+    #[serde(default)]
+    pub left_value: serde_yml::Value,
+    #[serde(default)]
+    pub right_value: serde_yml::Value,
+    /// Tag of the left value as written, e.g. `!Ref`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left_tag: Option<String>,
+    /// Tag of the right value as written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right_tag: Option<String>,
 }
 
 impl DiffValue {
-    pub(crate) fn new(left_value: serde_yml::Value, right_value: serde_yml::Value) -> Self {
+    pub(crate) fn new(left: &serde_yml::Value, right: &serde_yml::Value) -> Self {
         Self {
-            left_value,
-            right_value,
+            left_value: unwrap_tagged(left).clone(),
+            right_value: unwrap_tagged(right).clone(),
+            left_tag: tag_of(left),
+            right_tag: tag_of(right),
         }
     }
+
+    /// The left value with its tag, as written.
+    pub fn left_tagged(&self) -> serde_yml::Value {
+        with_tag(&self.left_value, &self.left_tag)
+    }
+
+    /// The right value with its tag, as written.
+    pub fn right_tagged(&self) -> serde_yml::Value {
+        with_tag(&self.right_value, &self.right_tag)
+    }
 }
 
-#[derive(Clone, Debug)]
-#[cfg_attr(test, derive(serde::Serialize))]
-pub(crate) struct YamlDiff {
-    pub(crate) key: Option<String>,
-    pub(crate) diff: DiffValue,
-    pub(crate) has_diff: bool,
-    pub(crate) diff_type: DiffType,
-    pub(crate) children: Vec<YamlDiff>,
+pub(crate) fn with_tag(value: &serde_yml::Value, tag: &Option<String>) -> serde_yml::Value {
+    match tag {
+        Some(tag) => serde_yml::Value::Tagged(Box::new(serde_yml::value::TaggedValue {
+            tag: serde_yml::value::Tag::new(tag.clone()),
+            value: value.clone(),
+        })),
+        None => value.clone(),
+    }
+}
+
+/// A node of the diff tree: a mapping member, a sequence element or a bare
+/// scalar, with `has_diff` set when anything at or below it changed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct YamlDiff {
+    pub key: Option<String>,
+    /// Full path of the node from the root of its document. Sequence
+    /// elements are indexed by their position on the left, or on the right
+    /// for additions. In a multi-document diff, paths restart at each
+    /// document, which `document` tells apart.
+    #[serde(default)]
+    pub path: Vec<PathSegment>,
+    /// Key of the top-level node of the document this node belongs to in
+    /// a multi-document diff: the document index, or the resource identity
+    /// in Kubernetes mode. `None` in a single-document diff.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document: Option<String>,
+    /// `path` as an RFC 6901 JSON Pointer, e.g. `/spec/containers/0`.
+    #[serde(default)]
+    pub pointer: String,
+    /// `path` as a yq expression, e.g. `.spec.containers[0]`.
+    #[serde(default)]
+    pub dotted_path: String,
+    pub diff: DiffValue,
+    pub has_diff: bool,
+    pub diff_type: DiffType,
+    #[serde(default)]
+    pub children: Vec<YamlDiff>,
+    /// Set when the value's tag changed, e.g. from `!Ref` to `!Sub`. The
+    /// node is then `modified` even if the value itself is equal.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub tag_changed: bool,
+    /// Set on a CloudFormation resource whose `Type` changed: it is a
+    /// different resource under the same logical ID, which is replaced
+    /// rather than updated. See `DiffOptions::cloudformation`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replaced: bool,
+    /// Line-level diff of a changed multi-line string, for rendering it
+    /// like a unified diff. `None` for other nodes, and for strings whose
+    /// line counts multiply past `DiffOptions::seq_diff_product_limit`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_hunks: Option<Vec<LineHunk>>,
+    /// Changed parts of a changed single-line string, at the granularity
+    /// set by `DiffOptions::inline_granularity`. `None` for other nodes,
+    /// and for strings whose token counts multiply past
+    /// `DiffOptions::seq_diff_product_limit`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inline_changes: Option<InlineChanges>,
+    /// Identity field(s) the elements of this node's sequence were matched
+    /// by, comma-separated when composite. `None` when the elements were
+    /// matched by position or as a multiset, for a root sequence, which has
+    /// no node of its own, and for mappings, whose members always match by
+    /// key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub match_key: Option<String>,
+    /// Index of a sequence element in the left sequence, or of a document
+    /// in a multi-document stream; `None` for additions and for other
+    /// nodes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left_index: Option<usize>,
+    /// Index of a sequence element in the right sequence, or of a document
+    /// in a multi-document stream; `None` for deletions and for other
+    /// nodes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right_index: Option<usize>,
+    /// Where the value is in the left source text, when the diff was made
+    /// from text and the value is there. See `source::attach_spans`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left_span: Option<Span>,
+    /// Where the value is in the right source text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right_span: Option<Span>,
+}
+
+/// Version of the serialized diff format, raised on incompatible changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// A diff tree in its stable serialized form, as returned by `compute_diff`
+/// and written by `yamalyze diff --format yaml`:
+/// `{ "format_version": 1, "diffs": [...] }`. Reading one written by a
+/// newer format version fails instead of misreading it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DiffReport {
+    #[serde(deserialize_with = "supported_format_version")]
+    pub format_version: u32,
+    pub diffs: Vec<YamlDiff>,
+}
+
+impl DiffReport {
+    pub fn new(diffs: Vec<YamlDiff>) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            diffs,
+        }
+    }
+}
+
+fn supported_format_version<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let version = u32::deserialize(deserializer)?;
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(de::Error::custom(format!(
+            "unsupported format_version {version}, expected at most {FORMAT_VERSION}"
+        )));
+    }
+    Ok(version)
 }
 
 impl YamlDiff {
     pub(crate) fn new(
         key: Option<String>,
+        path: &[PathSegment],
         diff: DiffValue,
         diff_type: DiffType,
         has_diff: bool,
@@ -52,68 +197,54 @@ impl YamlDiff {
     ) -> Self {
         Self {
             key,
+            path: path.to_vec(),
+            document: None,
+            pointer: json_pointer(path),
+            dotted_path: dotted_path(path),
             diff,
             has_diff,
             diff_type,
             children,
+            tag_changed: false,
+            replaced: false,
+            line_hunks: None,
+            inline_changes: None,
+            match_key: None,
+            left_index: None,
+            right_index: None,
+            left_span: None,
+            right_span: None,
         }
     }
-}
-
-/// Convert a YamlDiff node to a plain JS object. Crosses the WASM boundary
-/// once instead of requiring repeated getter calls that clone on every access.
-pub(crate) fn diff_node_to_js(node: &YamlDiff) -> Result<JsValue, JsValue> {
-    let obj = js_sys::Object::new();
 
-    let key_val = match &node.key {
-        Some(k) => JsValue::from_str(k),
-        None => JsValue::NULL,
-    };
-    js_sys::Reflect::set(&obj, &JsValue::from_str("key"), &key_val)?;
-    js_sys::Reflect::set(
-        &obj,
-        &JsValue::from_str("has_diff"),
-        &JsValue::from_bool(node.has_diff),
-    )?;
-
-    let dt: u32 = match node.diff_type {
-        DiffType::Unchanged => 0,
-        DiffType::Additions => 1,
-        DiffType::Deletions => 2,
-        DiffType::Modified => 3,
-    };
-    js_sys::Reflect::set(&obj, &JsValue::from_str("diff_type"), &JsValue::from(dt))?;
-
-    let diff_obj = js_sys::Object::new();
-    let lv = to_js(&node.diff.left_value)?;
-    let rv = to_js(&node.diff.right_value)?;
-    js_sys::Reflect::set(&diff_obj, &JsValue::from_str("left_value"), &lv)?;
-    js_sys::Reflect::set(&diff_obj, &JsValue::from_str("right_value"), &rv)?;
-    js_sys::Reflect::set(&obj, &JsValue::from_str("diff"), &diff_obj)?;
-
-    let children_arr = js_sys::Array::new();
-    for child in &node.children {
-        children_arr.push(&diff_node_to_js(child)?);
+    /// Record where a sequence element or document sits on either side.
+    pub(crate) fn at(mut self, left_index: Option<usize>, right_index: Option<usize>) -> Self {
+        self.left_index = left_index;
+        self.right_index = right_index;
+        self
     }
-    js_sys::Reflect::set(&obj, &JsValue::from_str("children"), &children_arr)?;
-
-    Ok(obj.into())
-}
 
-/// Convert a Vec<YamlDiff> to a JS array of plain objects.
-pub(crate) fn diff_vec_to_js(diffs: &[YamlDiff]) -> Result<JsValue, JsValue> {
-    let arr = js_sys::Array::new();
-    for node in diffs {
-        arr.push(&diff_node_to_js(node)?);
+    /// Mark this top-level node of a multi-document diff, and everything
+    /// below it, as belonging to the document it is keyed by.
+    pub(crate) fn in_document(mut self) -> Self {
+        fn mark(node: &mut YamlDiff, document: &Option<String>) {
+            node.document = document.clone();
+            for child in &mut node.children {
+                mark(child, document);
+            }
+        }
+        let document = self.key.clone();
+        mark(&mut self, &document);
+        self
     }
-    Ok(arr.into())
 }
 
 /// Strip `Value::Tagged` wrappers so the inner value is used for
 /// comparison, serialization, and type dispatch. serde_yml may parse
 /// certain scalars (e.g. version strings like `3.0.1`) as Tagged,
 /// and `TaggedValue::serialize` emits a mapping `{tag: value}` which
-/// confuses both the diff logic and JS rendering.
+/// confuses both the diff logic and JS rendering, so tags are compared
+/// and reported separately, see [`tag_of`].
 pub(crate) fn unwrap_tagged(value: &serde_yml::Value) -> &serde_yml::Value {
     match value {
         serde_yml::Value::Tagged(tagged) => unwrap_tagged(&tagged.value),
@@ -121,9 +252,25 @@ pub(crate) fn unwrap_tagged(value: &serde_yml::Value) -> &serde_yml::Value {
     }
 }
 
-pub(crate) fn to_js(value: &serde_yml::Value) -> Result<JsValue, JsValue> {
-    serde_wasm_bindgen::to_value(unwrap_tagged(value))
-        .map_err(|e| JsValue::from_str(&format!("Serialization error: {e}")))
+pub(crate) fn unwrap_tagged_mut(value: &mut serde_yml::Value) -> &mut serde_yml::Value {
+    match value {
+        serde_yml::Value::Tagged(tagged) => unwrap_tagged_mut(&mut tagged.value),
+        other => other,
+    }
+}
+
+/// The tag a value is written with, `None` for an untagged value. Core
+/// tags such as `!!str` are resolved by the parser and never show up here.
+pub(crate) fn tag_of(value: &serde_yml::Value) -> Option<String> {
+    match value {
+        serde_yml::Value::Tagged(tagged) => Some(tagged.tag.to_string()),
+        _ => None,
+    }
+}
+
+/// Whether the tags of two values count as a change under `options`.
+fn tag_changed(left: &serde_yml::Value, right: &serde_yml::Value, options: &DiffOptions) -> bool {
+    !options.strip_tags && tag_of(left) != tag_of(right)
 }
 
 pub(crate) fn yaml_key_to_string(key: &serde_yml::Value) -> String {
@@ -138,7 +285,7 @@ pub(crate) fn yaml_key_to_string(key: &serde_yml::Value) -> String {
 
 /// Serialize a YAML value to a canonical string for use as a comparison key
 /// in the Myers diff algorithm.
-fn serialize_value(v: &serde_yml::Value) -> String {
+pub(crate) fn serialize_value(v: &serde_yml::Value) -> String {
     let v = unwrap_tagged(v);
     serde_yml::to_string(v).unwrap_or_else(|_| format!("{v:?}"))
 }
@@ -148,17 +295,18 @@ fn serialize_value(v: &serde_yml::Value) -> String {
 /// expandable trees instead of flat `{}` / `[...]`.
 fn value_to_diff_children(
     value: &serde_yml::Value,
+    path: &[PathSegment],
     diff_type: &DiffType,
     depth: usize,
+    max_depth: usize,
 ) -> Vec<YamlDiff> {
-    if depth > MAX_DEPTH {
+    if depth > max_depth {
         return Vec::new();
     }
     let make_diff = |v: &serde_yml::Value| -> DiffValue {
-        let v = unwrap_tagged(v).clone();
         match diff_type {
-            DiffType::Deletions => DiffValue::new(v, serde_yml::Value::Null),
-            DiffType::Additions => DiffValue::new(serde_yml::Value::Null, v),
+            DiffType::Deletions => DiffValue::new(v, &serde_yml::Value::Null),
+            DiffType::Additions => DiffValue::new(&serde_yml::Value::Null, v),
             _ => unreachable!(),
         }
     };
@@ -167,9 +315,12 @@ fn value_to_diff_children(
         serde_yml::Value::Mapping(map) => {
             let mut children = Vec::new();
             for (key, val) in map.iter() {
-                let sub = value_to_diff_children(val, diff_type, depth + 1);
+                let key = yaml_key_to_string(key);
+                let path = [path, &[PathSegment::Key(key.clone())]].concat();
+                let sub = value_to_diff_children(val, &path, diff_type, depth + 1, max_depth);
                 children.push(YamlDiff::new(
-                    Some(yaml_key_to_string(key)),
+                    Some(key),
+                    &path,
                     make_diff(val),
                     diff_type.clone(),
                     true,
@@ -181,9 +332,11 @@ fn value_to_diff_children(
         serde_yml::Value::Sequence(seq) => {
             let mut children = Vec::new();
             for (i, val) in seq.iter().enumerate() {
-                let sub = value_to_diff_children(val, diff_type, depth + 1);
+                let path = [path, &[PathSegment::Index(i)]].concat();
+                let sub = value_to_diff_children(val, &path, diff_type, depth + 1, max_depth);
                 children.push(YamlDiff::new(
                     Some(i.to_string()),
+                    &path,
                     make_diff(val),
                     diff_type.clone(),
                     true,
@@ -196,61 +349,167 @@ fn value_to_diff_children(
     }
 }
 
+/// Options plus the position of the current node: its path from the root
+/// of its document and its nesting depth across the whole stream.
+#[derive(Clone, Debug)]
+pub(crate) struct DiffContext<'a> {
+    pub(crate) options: &'a DiffOptions,
+    pub(crate) path: Vec<PathSegment>,
+    pub(crate) depth: usize,
+}
+
+impl<'a> DiffContext<'a> {
+    pub(crate) fn new(options: &'a DiffOptions) -> Self {
+        Self {
+            options,
+            path: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    pub(crate) fn child(&self, segment: PathSegment) -> Self {
+        let mut path = self.path.clone();
+        path.push(segment);
+        Self {
+            options: self.options,
+            path,
+            depth: self.depth + 1,
+        }
+    }
+
+    /// Whether this node matches one of the ignore patterns.
+    pub(crate) fn is_ignored(&self) -> bool {
+        self.options.is_ignored(&self.path)
+    }
+
+    /// Context for the root of one document in a multi-document stream.
+    /// Paths start over at each document, so path patterns apply to all of
+    /// them; `YamlDiff::in_document` records which one a node belongs to.
+    pub(crate) fn document(&self) -> Self {
+        Self {
+            options: self.options,
+            path: Vec::new(),
+            depth: self.depth + 1,
+        }
+    }
+}
+
+/// Build the node for a value present on both sides, recursing into it so
+/// `has_diff` and the diff type reflect any change further down.
+pub(crate) fn paired_node(
+    key: Option<String>,
+    left: &serde_yml::Value,
+    right: &serde_yml::Value,
+    ctx: &DiffContext,
+) -> Result<YamlDiff, DiffError> {
+    let (child_diffs, match_key) = diff_with_match_key(left, right, ctx)?;
+    let tag_changed = tag_changed(left, right, ctx.options);
+    let has_diff = tag_changed || child_diffs.iter().any(|c| c.has_diff);
+    let diff_type = if has_diff {
+        DiffType::Modified
+    } else {
+        DiffType::Unchanged
+    };
+    let mut node = YamlDiff::new(
+        key,
+        &ctx.path,
+        DiffValue::new(left, right),
+        diff_type,
+        has_diff,
+        child_diffs,
+    );
+    node.match_key = match_key;
+    node.tag_changed = tag_changed;
+    Ok(node)
+}
+
+/// Build the node for a sequence element that moved from one index to
+/// another, diffed recursively in case it also changed on the way.
+fn moved_node(
+    key: Option<String>,
+    left: &serde_yml::Value,
+    right: &serde_yml::Value,
+    (left_index, right_index): (usize, usize),
+    ctx: &DiffContext,
+) -> Result<YamlDiff, DiffError> {
+    let mut node = paired_node(key, left, right, ctx)?.at(Some(left_index), Some(right_index));
+    node.diff_type = DiffType::Moved;
+    node.has_diff = true;
+    Ok(node)
+}
+
+/// Build the node for a value that only exists on the left side.
+pub(crate) fn deleted_node(
+    key: Option<String>,
+    value: &serde_yml::Value,
+    ctx: &DiffContext,
+) -> YamlDiff {
+    YamlDiff::new(
+        key,
+        &ctx.path,
+        DiffValue::new(value, &serde_yml::Value::Null),
+        DiffType::Deletions,
+        true,
+        value_to_diff_children(
+            value,
+            &ctx.path,
+            &DiffType::Deletions,
+            ctx.depth,
+            ctx.options.max_depth,
+        ),
+    )
+}
+
+/// Build the node for a value that only exists on the right side.
+pub(crate) fn added_node(
+    key: Option<String>,
+    value: &serde_yml::Value,
+    ctx: &DiffContext,
+) -> YamlDiff {
+    YamlDiff::new(
+        key,
+        &ctx.path,
+        DiffValue::new(&serde_yml::Value::Null, value),
+        DiffType::Additions,
+        true,
+        value_to_diff_children(
+            value,
+            &ctx.path,
+            &DiffType::Additions,
+            ctx.depth,
+            ctx.options.max_depth,
+        ),
+    )
+}
+
 fn map_diff(
     left: &serde_yml::Mapping,
     right: &serde_yml::Mapping,
-    depth: usize,
-) -> Result<Vec<YamlDiff>, JsValue> {
+    ctx: &DiffContext,
+) -> Result<Vec<YamlDiff>, DiffError> {
     let mut diffs: Vec<YamlDiff> = Vec::new();
 
     for (key, value_one) in left.iter() {
         let key_str = yaml_key_to_string(key);
+        let child = ctx.child(PathSegment::Key(key_str.clone()));
+        if child.is_ignored() {
+            continue;
+        }
         match right.get(key) {
             Some(value_two) => {
-                let child_diffs = yaml_diff(value_one, value_two, depth + 1)?;
-                let any_child_has_diff = child_diffs.iter().any(|c| c.has_diff);
-                diffs.push(YamlDiff {
-                    key: Some(key_str),
-                    diff: DiffValue {
-                        left_value: unwrap_tagged(value_one).clone(),
-                        right_value: unwrap_tagged(value_two).clone(),
-                    },
-                    diff_type: if any_child_has_diff {
-                        DiffType::Modified
-                    } else {
-                        DiffType::Unchanged
-                    },
-                    has_diff: any_child_has_diff,
-                    children: child_diffs,
-                });
-            }
-            None => {
-                diffs.push(YamlDiff {
-                    key: Some(key_str),
-                    diff: DiffValue {
-                        left_value: unwrap_tagged(value_one).clone(),
-                        right_value: serde_yml::Value::Null,
-                    },
-                    diff_type: DiffType::Deletions,
-                    has_diff: true,
-                    children: value_to_diff_children(value_one, &DiffType::Deletions, depth + 1),
-                });
+                diffs.push(paired_node(Some(key_str), value_one, value_two, &child)?)
             }
+            None => diffs.push(deleted_node(Some(key_str), value_one, &child)),
         }
     }
 
     for (key, value_two) in right.iter() {
         if !left.contains_key(key) {
-            diffs.push(YamlDiff {
-                key: Some(yaml_key_to_string(key)),
-                diff: DiffValue {
-                    left_value: serde_yml::Value::Null,
-                    right_value: unwrap_tagged(value_two).clone(),
-                },
-                diff_type: DiffType::Additions,
-                has_diff: true,
-                children: value_to_diff_children(value_two, &DiffType::Additions, depth + 1),
-            });
+            let key_str = yaml_key_to_string(key);
+            let child = ctx.child(PathSegment::Key(key_str.clone()));
+            if !child.is_ignored() {
+                diffs.push(added_node(Some(key_str), value_two, &child));
+            }
         }
     }
 
@@ -260,57 +519,25 @@ fn map_diff(
 /// Positional element-by-element comparison for very large sequences where
 /// Myers diff would be too expensive.
 fn positional_seq_diff(
-    left: &serde_yml::Sequence,
-    right: &serde_yml::Sequence,
-    depth: usize,
-) -> Result<Vec<YamlDiff>, JsValue> {
+    left: &[serde_yml::Value],
+    right: &[serde_yml::Value],
+    ctx: &DiffContext,
+) -> Result<Vec<YamlDiff>, DiffError> {
     let mut diffs: Vec<YamlDiff> = Vec::new();
     let max_len = std::cmp::max(left.len(), right.len());
 
     for i in 0..max_len {
+        let key = Some(i.to_string());
+        let child = ctx.child(PathSegment::Index(i));
+        if child.is_ignored() {
+            continue;
+        }
         match (left.get(i), right.get(i)) {
             (Some(lv), Some(rv)) => {
-                let child_diffs = yaml_diff(lv, rv, depth + 1)?;
-                let any_child_has_diff = child_diffs.iter().any(|c| c.has_diff);
-                diffs.push(YamlDiff {
-                    key: Some(i.to_string()),
-                    diff: DiffValue {
-                        left_value: unwrap_tagged(lv).clone(),
-                        right_value: unwrap_tagged(rv).clone(),
-                    },
-                    diff_type: if any_child_has_diff {
-                        DiffType::Modified
-                    } else {
-                        DiffType::Unchanged
-                    },
-                    has_diff: any_child_has_diff,
-                    children: child_diffs,
-                });
-            }
-            (Some(lv), None) => {
-                diffs.push(YamlDiff {
-                    key: Some(i.to_string()),
-                    diff: DiffValue {
-                        left_value: unwrap_tagged(lv).clone(),
-                        right_value: serde_yml::Value::Null,
-                    },
-                    diff_type: DiffType::Deletions,
-                    has_diff: true,
-                    children: value_to_diff_children(lv, &DiffType::Deletions, depth + 1),
-                });
-            }
-            (None, Some(rv)) => {
-                diffs.push(YamlDiff {
-                    key: Some(i.to_string()),
-                    diff: DiffValue {
-                        left_value: serde_yml::Value::Null,
-                        right_value: unwrap_tagged(rv).clone(),
-                    },
-                    diff_type: DiffType::Additions,
-                    has_diff: true,
-                    children: value_to_diff_children(rv, &DiffType::Additions, depth + 1),
-                });
+                diffs.push(paired_node(key, lv, rv, &child)?.at(Some(i), Some(i)))
             }
+            (Some(lv), None) => diffs.push(deleted_node(key, lv, &child).at(Some(i), None)),
+            (None, Some(rv)) => diffs.push(added_node(key, rv, &child).at(None, Some(i))),
             (None, None) => unreachable!(),
         }
     }
@@ -318,161 +545,267 @@ fn positional_seq_diff(
     Ok(diffs)
 }
 
-fn seq_diff(
+/// Identity of a sequence element for keyed matching: the values of the
+/// key fields when the element is a mapping that has all of them, or the
+/// whole serialized element otherwise. The prefixes keep the two kinds
+/// from ever colliding.
+pub(crate) fn element_identity(value: &serde_yml::Value, fields: &[String]) -> String {
+    let keyed = unwrap_tagged(value).as_mapping().and_then(|map| {
+        fields
+            .iter()
+            .map(|f| map.get(f.as_str()).map(serialize_value))
+            .collect::<Option<Vec<_>>>()
+    });
+    match keyed {
+        Some(parts) => format!("key\0{}", parts.join("\0")),
+        None => format!("value\0{}", serialize_value(value)),
+    }
+}
+
+/// Look for a field every element on both sides has, with a scalar value
+/// that is unique within each side, so elements can be matched by it.
+/// Only sequences made up entirely of mappings qualify.
+fn detect_sequence_key(
     left: &serde_yml::Sequence,
     right: &serde_yml::Sequence,
-    depth: usize,
-) -> Result<Vec<YamlDiff>, JsValue> {
-    // Size guard: fall back to positional comparison for very large sequences
-    if left.len().saturating_mul(right.len()) > SEQ_DIFF_PRODUCT_LIMIT {
-        return positional_seq_diff(left, right, depth);
+) -> Option<&'static str> {
+    if left.is_empty() || right.is_empty() {
+        return None;
+    }
+    let unique_on = |seq: &serde_yml::Sequence, field: &str| -> bool {
+        let mut seen = HashSet::new();
+        seq.iter().all(|element| {
+            let value = unwrap_tagged(element)
+                .as_mapping()
+                .and_then(|m| m.get(field));
+            match value.map(unwrap_tagged) {
+                Some(
+                    v @ (serde_yml::Value::String(_)
+                    | serde_yml::Value::Number(_)
+                    | serde_yml::Value::Bool(_)),
+                ) => seen.insert(serialize_value(v)),
+                _ => false,
+            }
+        })
+    };
+    AUTO_KEY_CANDIDATES
+        .into_iter()
+        .find(|field| unique_on(left, field) && unique_on(right, field))
+}
+
+/// Identity fields to match the elements of these sequences by: the ones
+/// configured for this path, else a detected key when detection is on.
+pub(crate) fn sequence_match_fields(
+    left: &serde_yml::Sequence,
+    right: &serde_yml::Sequence,
+    ctx: &DiffContext,
+) -> Option<Vec<String>> {
+    if let Some(fields) = ctx.options.sequence_key_for(&ctx.path) {
+        return Some(fields.to_vec());
+    }
+    if ctx.options.detect_sequence_keys {
+        return detect_sequence_key(left, right).map(|field| vec![field.to_string()]);
+    }
+    None
+}
+
+/// Where the element at a right-hand index came from on the left.
+#[derive(Clone, Copy)]
+enum Partner {
+    /// Replaced at the same spot in the sequence.
+    InPlace(usize),
+    /// Taken from a different spot in the sequence.
+    Moved(usize),
+}
+
+/// Pair leftover elements that were removed in one place and inserted in
+/// another when their serialized forms are similar enough, best matches
+/// first. These are moves with edits.
+fn pair_similar_elements(
+    left_strs: &[String],
+    right_strs: &[String],
+    left_pending: &[usize],
+    right_pending: &[usize],
+    partners: &mut HashMap<usize, Partner>,
+) {
+    if left_pending.len().saturating_mul(right_pending.len()) > MOVE_SEARCH_LIMIT {
+        return;
+    }
+    let mut candidates: Vec<(f32, usize, usize)> = Vec::new();
+    for &li in left_pending {
+        for &ri in right_pending {
+            let ratio = similar::TextDiff::from_lines(&left_strs[li], &right_strs[ri]).ratio();
+            if ratio >= MOVE_SIMILARITY_THRESHOLD {
+                candidates.push((ratio, li, ri));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut taken_left: HashSet<usize> = HashSet::new();
+    for (_, li, ri) in candidates {
+        if !taken_left.contains(&li) && !partners.contains_key(&ri) {
+            taken_left.insert(li);
+            partners.insert(ri, Partner::Moved(li));
+        }
+    }
+}
+
+/// Diff two sequences given an identity string per element. Myers over the
+/// identities keeps in-order matches aligned. Elements Myers left unmatched
+/// are then paired in three passes: equal identities in different places
+/// become moves; what remains of each replaced block is paired in place,
+/// only among elements without the key fields when `keyed`; and unless
+/// `keyed`, similar elements elsewhere become moves with edits.
+/// Moves are reported at their position in the right sequence.
+fn aligned_seq_diff(
+    left: &serde_yml::Sequence,
+    right: &serde_yml::Sequence,
+    left_ids: &[String],
+    right_ids: &[String],
+    keyed: bool,
+    ctx: &DiffContext,
+) -> Result<Vec<YamlDiff>, DiffError> {
+    let ops = similar::capture_diff_slices(similar::Algorithm::Myers, left_ids, right_ids);
+    let changed = || {
+        ops.iter()
+            .filter(|op| !matches!(op, similar::DiffOp::Equal { .. }))
+    };
+
+    let mut partners: HashMap<usize, Partner> = HashMap::new();
+
+    let mut unmatched_left: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for op in changed() {
+        for li in op.old_range() {
+            unmatched_left
+                .entry(&left_ids[li])
+                .or_default()
+                .push_back(li);
+        }
+    }
+    for op in changed() {
+        for ri in op.new_range() {
+            let candidate = unmatched_left
+                .get_mut(right_ids[ri].as_str())
+                .and_then(|queue| queue.pop_front());
+            if let Some(li) = candidate {
+                partners.insert(ri, Partner::Moved(li));
+            }
+        }
+    }
+
+    let mut consumed_left: HashSet<usize> = partners
+        .values()
+        .map(|p| match *p {
+            Partner::InPlace(li) | Partner::Moved(li) => li,
+        })
+        .collect();
+
+    // Keyed elements with different keys are different elements; only
+    // those without the key fields are compared by value and can change
+    // in place.
+    let pairable = |id: &str| !keyed || id.starts_with("value\0");
+    for op in changed() {
+        let olds = op
+            .old_range()
+            .filter(|&li| !consumed_left.contains(&li) && pairable(&left_ids[li]));
+        let news = op
+            .new_range()
+            .filter(|&ri| !partners.contains_key(&ri) && pairable(&right_ids[ri]));
+        let pairs: Vec<(usize, usize)> = olds.zip(news).collect();
+        for (li, ri) in pairs {
+            partners.insert(ri, Partner::InPlace(li));
+            consumed_left.insert(li);
+        }
     }
 
-    let left_strs: Vec<String> = left.iter().map(serialize_value).collect();
-    let right_strs: Vec<String> = right.iter().map(serialize_value).collect();
-    let ops = similar::capture_diff_slices(similar::Algorithm::Myers, &left_strs, &right_strs);
+    if !keyed {
+        let left_pending: Vec<usize> = changed()
+            .flat_map(|op| op.old_range())
+            .filter(|li| !consumed_left.contains(li))
+            .collect();
+        let right_pending: Vec<usize> = changed()
+            .flat_map(|op| op.new_range())
+            .filter(|ri| !partners.contains_key(ri))
+            .collect();
+        pair_similar_elements(
+            left_ids,
+            right_ids,
+            &left_pending,
+            &right_pending,
+            &mut partners,
+        );
+        for &ri in &right_pending {
+            if let Some(Partner::Moved(li)) = partners.get(&ri) {
+                consumed_left.insert(*li);
+            }
+        }
+    }
 
     let mut diffs: Vec<YamlDiff> = Vec::new();
     let mut pos: usize = 0;
+    let mut next_key = || {
+        let key = Some(pos.to_string());
+        pos += 1;
+        key
+    };
 
-    for op in ops {
-        match op {
-            similar::DiffOp::Equal {
-                old_index,
-                new_index,
-                len,
-            } => {
-                for i in 0..len {
-                    let li = old_index + i;
-                    let ri = new_index + i;
-                    let child_diffs = yaml_diff(&left[li], &right[ri], depth + 1)?;
-                    let any_child_has_diff = child_diffs.iter().any(|c| c.has_diff);
-                    diffs.push(YamlDiff {
-                        key: Some(pos.to_string()),
-                        diff: DiffValue {
-                            left_value: unwrap_tagged(&left[li]).clone(),
-                            right_value: unwrap_tagged(&right[ri]).clone(),
-                        },
-                        diff_type: if any_child_has_diff {
-                            DiffType::Modified
-                        } else {
-                            DiffType::Unchanged
-                        },
-                        has_diff: any_child_has_diff,
-                        children: child_diffs,
-                    });
-                    pos += 1;
+    for op in &ops {
+        if let similar::DiffOp::Equal {
+            old_index,
+            new_index,
+            len,
+        } = *op
+        {
+            for i in 0..len {
+                let (li, ri) = (old_index + i, new_index + i);
+                let key = next_key();
+                let child = ctx.child(PathSegment::Index(li));
+                if !child.is_ignored() {
+                    diffs.push(
+                        paired_node(key, &left[li], &right[ri], &child)?.at(Some(li), Some(ri)),
+                    );
                 }
             }
-            similar::DiffOp::Delete {
-                old_index, old_len, ..
-            } => {
-                for i in 0..old_len {
-                    let li = old_index + i;
-                    diffs.push(YamlDiff {
-                        key: Some(pos.to_string()),
-                        diff: DiffValue {
-                            left_value: unwrap_tagged(&left[li]).clone(),
-                            right_value: serde_yml::Value::Null,
-                        },
-                        diff_type: DiffType::Deletions,
-                        has_diff: true,
-                        children: value_to_diff_children(
-                            &left[li],
-                            &DiffType::Deletions,
-                            depth + 1,
-                        ),
-                    });
-                    pos += 1;
+            continue;
+        }
+        // In-place pairs first, then what was removed, then what arrived.
+        for ri in op.new_range() {
+            if let Some(Partner::InPlace(li)) = partners.get(&ri) {
+                let key = next_key();
+                let child = ctx.child(PathSegment::Index(*li));
+                if !child.is_ignored() {
+                    diffs.push(
+                        paired_node(key, &left[*li], &right[ri], &child)?.at(Some(*li), Some(ri)),
+                    );
                 }
             }
-            similar::DiffOp::Insert {
-                new_index, new_len, ..
-            } => {
-                for i in 0..new_len {
-                    let ri = new_index + i;
-                    diffs.push(YamlDiff {
-                        key: Some(pos.to_string()),
-                        diff: DiffValue {
-                            left_value: serde_yml::Value::Null,
-                            right_value: unwrap_tagged(&right[ri]).clone(),
-                        },
-                        diff_type: DiffType::Additions,
-                        has_diff: true,
-                        children: value_to_diff_children(
-                            &right[ri],
-                            &DiffType::Additions,
-                            depth + 1,
-                        ),
-                    });
-                    pos += 1;
+        }
+        for li in op.old_range() {
+            if !consumed_left.contains(&li) {
+                let key = next_key();
+                let child = ctx.child(PathSegment::Index(li));
+                if !child.is_ignored() {
+                    diffs.push(deleted_node(key, &left[li], &child).at(Some(li), None));
                 }
             }
-            similar::DiffOp::Replace {
-                old_index,
-                old_len,
-                new_index,
-                new_len,
-            } => {
-                // Pair elements positionally for internal diff visibility,
-                // then emit remaining unpaired elements as additions/deletions.
-                let common = std::cmp::min(old_len, new_len);
-                for i in 0..common {
-                    let li = old_index + i;
-                    let ri = new_index + i;
-                    let child_diffs = yaml_diff(&left[li], &right[ri], depth + 1)?;
-                    let any_child_has_diff = child_diffs.iter().any(|c| c.has_diff);
-                    diffs.push(YamlDiff {
-                        key: Some(pos.to_string()),
-                        diff: DiffValue {
-                            left_value: unwrap_tagged(&left[li]).clone(),
-                            right_value: unwrap_tagged(&right[ri]).clone(),
-                        },
-                        diff_type: if any_child_has_diff {
-                            DiffType::Modified
-                        } else {
-                            DiffType::Unchanged
-                        },
-                        has_diff: any_child_has_diff,
-                        children: child_diffs,
-                    });
-                    pos += 1;
-                }
-                for i in common..old_len {
-                    let li = old_index + i;
-                    diffs.push(YamlDiff {
-                        key: Some(pos.to_string()),
-                        diff: DiffValue {
-                            left_value: unwrap_tagged(&left[li]).clone(),
-                            right_value: serde_yml::Value::Null,
-                        },
-                        diff_type: DiffType::Deletions,
-                        has_diff: true,
-                        children: value_to_diff_children(
-                            &left[li],
-                            &DiffType::Deletions,
-                            depth + 1,
-                        ),
-                    });
-                    pos += 1;
+        }
+        for ri in op.new_range() {
+            match partners.get(&ri) {
+                Some(Partner::Moved(li)) => {
+                    let key = next_key();
+                    let child = ctx.child(PathSegment::Index(*li));
+                    if !child.is_ignored() {
+                        diffs.push(moved_node(key, &left[*li], &right[ri], (*li, ri), &child)?);
+                    }
                 }
-                for i in common..new_len {
-                    let ri = new_index + i;
-                    diffs.push(YamlDiff {
-                        key: Some(pos.to_string()),
-                        diff: DiffValue {
-                            left_value: serde_yml::Value::Null,
-                            right_value: unwrap_tagged(&right[ri]).clone(),
-                        },
-                        diff_type: DiffType::Additions,
-                        has_diff: true,
-                        children: value_to_diff_children(
-                            &right[ri],
-                            &DiffType::Additions,
-                            depth + 1,
-                        ),
-                    });
-                    pos += 1;
+                Some(Partner::InPlace(_)) => {}
+                None => {
+                    let key = next_key();
+                    let child = ctx.child(PathSegment::Index(ri));
+                    if !child.is_ignored() {
+                        diffs.push(added_node(key, &right[ri], &child).at(None, Some(ri)));
+                    }
                 }
             }
         }
@@ -481,58 +814,226 @@ fn seq_diff(
     Ok(diffs)
 }
 
-fn val_diff(left: &serde_yml::Value, right: &serde_yml::Value) -> Vec<YamlDiff> {
-    let has_diff = left != right;
+/// Compare two sequences as multisets. Each right element claims the
+/// first unclaimed equal element on the left, so duplicates are counted
+/// and reordering alone is no change. Left elements come first in their
+/// order, matched or deleted, followed by the right-only additions.
+fn multiset_seq_diff(
+    left: &serde_yml::Sequence,
+    right: &serde_yml::Sequence,
+    ctx: &DiffContext,
+) -> Result<Vec<YamlDiff>, DiffError> {
+    let mut unmatched_right: HashMap<String, VecDeque<usize>> = HashMap::new();
+    for (ri, value) in right.iter().enumerate() {
+        unmatched_right
+            .entry(serialize_value(value))
+            .or_default()
+            .push_back(ri);
+    }
+
+    let mut diffs: Vec<YamlDiff> = Vec::new();
+    let mut claimed_right: HashSet<usize> = HashSet::new();
+
+    for (li, value) in left.iter().enumerate() {
+        let key = Some(diffs.len().to_string());
+        let child = ctx.child(PathSegment::Index(li));
+        if child.is_ignored() {
+            continue;
+        }
+        let partner = unmatched_right
+            .get_mut(&serialize_value(value))
+            .and_then(|queue| queue.pop_front());
+        match partner {
+            Some(ri) => {
+                claimed_right.insert(ri);
+                diffs.push(paired_node(key, value, &right[ri], &child)?.at(Some(li), Some(ri)));
+            }
+            None => diffs.push(deleted_node(key, value, &child).at(Some(li), None)),
+        }
+    }
+
+    for (ri, value) in right.iter().enumerate() {
+        let child = ctx.child(PathSegment::Index(ri));
+        if !claimed_right.contains(&ri) && !child.is_ignored() {
+            let key = Some(diffs.len().to_string());
+            diffs.push(added_node(key, value, &child).at(None, Some(ri)));
+        }
+    }
+
+    Ok(diffs)
+}
+
+/// Diff two sequences, returning the element nodes and the identity
+/// field(s) they were matched by, comma-separated, when matching by
+/// identity actually ran.
+fn seq_diff(
+    left: &serde_yml::Sequence,
+    right: &serde_yml::Sequence,
+    ctx: &DiffContext,
+) -> Result<(Vec<YamlDiff>, Option<String>), DiffError> {
+    let mut match_key = None;
+    let mut diffs = if ctx.options.is_unordered(&ctx.path) {
+        multiset_seq_diff(left, right, ctx)?
+    } else if left.len().saturating_mul(right.len()) > ctx.options.seq_diff_product_limit {
+        // Size guard: fall back to positional comparison for very large sequences
+        positional_seq_diff(left, right, ctx)?
+    } else if let Some(fields) = sequence_match_fields(left, right, ctx) {
+        let left_ids: Vec<String> = left.iter().map(|v| element_identity(v, &fields)).collect();
+        let right_ids: Vec<String> = right.iter().map(|v| element_identity(v, &fields)).collect();
+        match_key = Some(fields.join(","));
+        aligned_seq_diff(left, right, &left_ids, &right_ids, true, ctx)?
+    } else {
+        let left_strs: Vec<String> = left.iter().map(serialize_value).collect();
+        let right_strs: Vec<String> = right.iter().map(serialize_value).collect();
+        aligned_seq_diff(left, right, &left_strs, &right_strs, false, ctx)?
+    };
+
+    // The diffs above are keyed by merged position.
+    let side_index = |node: &YamlDiff| match ctx.options.sequence_index {
+        SequenceIndex::Merged => None,
+        SequenceIndex::Left => node.left_index.or(node.right_index),
+        SequenceIndex::Right => node.right_index.or(node.left_index),
+    };
+    for node in &mut diffs {
+        if let Some(index) = side_index(node) {
+            node.key = Some(index.to_string());
+        }
+    }
+    Ok((diffs, match_key))
+}
+
+/// The bare node for two values that are not both mappings or both
+/// sequences, tags included.
+fn val_diff(left: &serde_yml::Value, right: &serde_yml::Value, ctx: &DiffContext) -> Vec<YamlDiff> {
+    let tag_changed = tag_changed(left, right, ctx.options);
+    let has_diff = tag_changed || unwrap_tagged(left) != unwrap_tagged(right);
     let diff_type = if !has_diff {
         DiffType::Unchanged
     } else {
-        match (left, right) {
+        match (unwrap_tagged(left), unwrap_tagged(right)) {
             (serde_yml::Value::Null, _) => DiffType::Additions,
             (_, serde_yml::Value::Null) => DiffType::Deletions,
             _ => DiffType::Modified,
         }
     };
 
-    vec![YamlDiff {
-        key: None,
-        diff: DiffValue {
-            left_value: left.clone(),
-            right_value: right.clone(),
-        },
-        has_diff,
+    let mut node = YamlDiff::new(
+        None,
+        &ctx.path,
+        DiffValue::new(left, right),
         diff_type,
-        children: Vec::new(),
-    }]
+        has_diff,
+        Vec::new(),
+    );
+    node.tag_changed = tag_changed;
+    if let (DiffType::Modified, Some(left), Some(right)) = (
+        &node.diff_type,
+        unwrap_tagged(left).as_str(),
+        unwrap_tagged(right).as_str(),
+    ) {
+        if is_multi_line(left, right) {
+            node.line_hunks = line_hunks(left, right, ctx.options.seq_diff_product_limit);
+        } else {
+            node.inline_changes = inline_changes(
+                left,
+                right,
+                ctx.options.inline_granularity,
+                ctx.options.seq_diff_product_limit,
+            );
+        }
+    }
+    vec![node]
 }
 
+/// The child nodes of two values. A root sequence's identity field is
+/// not reported, since no node holds the root; see `YamlDiff::match_key`.
 pub fn yaml_diff(
     left: &serde_yml::Value,
     right: &serde_yml::Value,
-    depth: usize,
-) -> Result<Vec<YamlDiff>, JsValue> {
-    if depth > MAX_DEPTH {
-        return Err(JsValue::from_str(
-            "Maximum nesting depth exceeded (256 levels)",
-        ));
-    }
+    ctx: &DiffContext,
+) -> Result<Vec<YamlDiff>, DiffError> {
+    diff_with_match_key(left, right, ctx).map(|(diffs, _)| diffs)
+}
 
-    let left = unwrap_tagged(left);
-    let right = unwrap_tagged(right);
+/// Like `yaml_diff`, plus the `match_key` of the node holding the values.
+fn diff_with_match_key(
+    left: &serde_yml::Value,
+    right: &serde_yml::Value,
+    ctx: &DiffContext,
+) -> Result<(Vec<YamlDiff>, Option<String>), DiffError> {
+    if ctx.depth > ctx.options.max_depth {
+        return Err(DiffError::MaxDepthExceeded {
+            max_depth: ctx.options.max_depth,
+        });
+    }
 
-    match (left, right) {
+    match (unwrap_tagged(left), unwrap_tagged(right)) {
         (serde_yml::Value::Mapping(map_one), serde_yml::Value::Mapping(map_two)) => {
-            map_diff(map_one, map_two, depth)
+            Ok((map_diff(map_one, map_two, ctx)?, None))
         }
         (serde_yml::Value::Sequence(seq_one), serde_yml::Value::Sequence(seq_two)) => {
-            seq_diff(seq_one, seq_two, depth)
+            seq_diff(seq_one, seq_two, ctx)
         }
-        (one, two) => Ok(val_diff(one, two)),
+        _ => Ok((val_diff(left, right, ctx), None)),
+    }
+}
+
+/// Diff two YAML streams document by document. A pair of single-document
+/// streams is diffed directly so the common case keeps its flat shape, and
+/// a single document against an empty stream, a file that was created or
+/// deleted, is reported whole in that same shape. Otherwise each document
+/// becomes a top-level node keyed by its index, with unmatched trailing
+/// documents reported as additions or deletions.
+pub fn documents_diff(
+    left: &[serde_yml::Value],
+    right: &[serde_yml::Value],
+    options: &DiffOptions,
+) -> Result<Vec<YamlDiff>, DiffError> {
+    let (left, right) = (
+        &*prepare_documents(left, options)?,
+        &*prepare_documents(right, options)?,
+    );
+    let ctx = DiffContext::new(options);
+    match (left, right) {
+        ([one], [two]) => return yaml_diff(one, two, &ctx),
+        ([], [two]) => return Ok(whole_document(added_node(None, two, &ctx))),
+        ([one], []) => return Ok(whole_document(deleted_node(None, one, &ctx))),
+        _ => {}
+    }
+
+    let mut diffs: Vec<YamlDiff> = Vec::new();
+    let max_len = std::cmp::max(left.len(), right.len());
+
+    for i in 0..max_len {
+        let key = Some(i.to_string());
+        let document = ctx.document();
+        let node = match (left.get(i), right.get(i)) {
+            (Some(lv), Some(rv)) => paired_node(key, lv, rv, &document)?.at(Some(i), Some(i)),
+            (Some(lv), None) => deleted_node(key, lv, &document).at(Some(i), None),
+            (None, Some(rv)) => added_node(key, rv, &document).at(None, Some(i)),
+            (None, None) => unreachable!(),
+        };
+        diffs.push(node.in_document());
+    }
+
+    Ok(diffs)
+}
+
+/// The members or elements of an added or deleted document, or the
+/// document itself when it is a scalar.
+fn whole_document(node: YamlDiff) -> Vec<YamlDiff> {
+    if node.children.is_empty() {
+        vec![node]
+    } else {
+        node.children
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::options::SequenceKey;
+    use crate::path::PathPattern;
 
     #[test]
     fn unwrap_tagged_strips_tag() {
@@ -545,6 +1046,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn unwrap_tagged_strips_explicit_tag() {
+        let value: serde_yml::Value = serde_yml::from_str("!Ref 3.0.1").unwrap();
+        let unwrapped = unwrap_tagged(&value);
+        assert!(
+            matches!(unwrapped, serde_yml::Value::String(s) if s == "3.0.1"),
+            "expected String(\"3.0.1\"), got {unwrapped:?}",
+        );
+        assert_eq!(tag_of(&value).as_deref(), Some("!Ref"));
+    }
+
     #[test]
     fn unwrap_tagged_passthrough() {
         let value: serde_yml::Value = serde_yml::from_str("hello").unwrap();
@@ -554,4 +1066,479 @@ mod tests {
             "expected String(\"hello\"), got {unwrapped:?}",
         );
     }
+
+    #[test]
+    fn changed_image_tags_mark_only_the_version() {
+        let diffs = diff_with(
+            "image: registry.example.com/team/api:1.25.0\n",
+            "image: registry.example.com/team/api:1.26.0\n",
+            &DiffOptions::default(),
+        );
+        let changes = diffs[0].children[0].inline_changes.as_ref().unwrap();
+        let version = changes.left.iter().map(|range| (range.start, range.end));
+        assert_eq!(version.collect::<Vec<_>>(), [(32, 34)]);
+        assert_eq!(changes.left, changes.right);
+    }
+
+    #[test]
+    fn tag_changes_are_modifications_unless_stripped() {
+        let diffs = diff_with("a: !Ref Foo\n", "a: !Sub Foo\n", &DiffOptions::default());
+        let node = &diffs[0];
+        assert_eq!(node.diff_type, DiffType::Modified);
+        assert!(node.tag_changed);
+        assert_eq!(node.diff.left_tag.as_deref(), Some("!Ref"));
+        assert_eq!(node.diff.right_tag.as_deref(), Some("!Sub"));
+        assert_eq!(node.diff.left_value, node.diff.right_value);
+
+        let stripped = DiffOptions {
+            strip_tags: true,
+            ..Default::default()
+        };
+        let diffs = diff_with("a: !Ref Foo\n", "a: !Sub Foo\n", &stripped);
+        assert!(!diffs[0].has_diff);
+        assert_eq!(diffs[0].diff.right_tag.as_deref(), Some("!Sub"));
+    }
+
+    #[test]
+    fn tagged_strings_get_text_diffs() {
+        let diffs = diff_with(
+            "a: !Sub 'arn:${Old}:x'\nb: !Sub |\n  one\n  two\n",
+            "a: !Sub 'arn:${New}:x'\nb: !Sub |\n  one\n  three\n",
+            &DiffOptions::default(),
+        );
+        assert!(diffs[0].children[0].inline_changes.is_some());
+        assert!(diffs[1].children[0].line_hunks.is_some());
+    }
+
+    #[test]
+    fn long_multi_line_strings_skip_line_hunks() {
+        let options = DiffOptions {
+            seq_diff_product_limit: 4,
+            ..Default::default()
+        };
+        let diffs = diff_with(
+            "a: |\n  one\n  two\n  three\n",
+            "a: |\n  one\n  2\n  three\n",
+            &options,
+        );
+        let node = &diffs[0].children[0];
+        assert_eq!(node.diff_type, DiffType::Modified);
+        assert!(node.line_hunks.is_none());
+        assert!(node.inline_changes.is_none());
+    }
+
+    #[test]
+    fn tagged_collections_diff_their_contents() {
+        let diffs = diff_with(
+            "a: !If [c, x, y]\n",
+            "a: !If [c, x, z]\n",
+            &DiffOptions::default(),
+        );
+        let node = &diffs[0];
+        assert!(!node.tag_changed);
+        assert_eq!(node.diff.left_tag.as_deref(), Some("!If"));
+        assert_eq!(
+            summary(&node.children),
+            vec![
+                (Some("0".to_string()), DiffType::Unchanged),
+                (Some("1".to_string()), DiffType::Unchanged),
+                (Some("2".to_string()), DiffType::Modified),
+            ]
+        );
+    }
+
+    fn diff_with(left: &str, right: &str, options: &DiffOptions) -> Vec<YamlDiff> {
+        let left: serde_yml::Value = serde_yml::from_str(left).unwrap();
+        let right: serde_yml::Value = serde_yml::from_str(right).unwrap();
+        yaml_diff(&left, &right, &DiffContext::new(options)).unwrap()
+    }
+
+    fn summary(nodes: &[YamlDiff]) -> Vec<(Option<String>, DiffType)> {
+        nodes
+            .iter()
+            .map(|n| (n.key.clone(), n.diff_type.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn keyed_sequence_pairs_changed_element() {
+        let options = DiffOptions {
+            sequence_keys: vec![SequenceKey::new("containers", &["name"])],
+            ..Default::default()
+        };
+        let diffs = diff_with(
+            "containers:\n  - name: app\n    image: app:1\n  - name: sidecar\n    image: proxy:1\n",
+            "containers:\n  - name: app\n    image: app:2\n  - name: sidecar\n    image: proxy:1\n",
+            &options,
+        );
+        assert_eq!(
+            summary(&diffs[0].children),
+            vec![
+                (Some("0".to_string()), DiffType::Modified),
+                (Some("1".to_string()), DiffType::Unchanged),
+            ]
+        );
+    }
+
+    #[test]
+    fn keyed_sequence_pairs_reordered_elements() {
+        let options = DiffOptions {
+            sequence_keys: vec![SequenceKey::new("*", &["name"])],
+            ..Default::default()
+        };
+        let diffs = diff_with(
+            "env:\n  - name: A\n    value: '1'\n  - name: B\n    value: '2'\n",
+            "env:\n  - name: B\n    value: '2'\n  - name: A\n    value: '3'\n",
+            &options,
+        );
+        let children = &diffs[0].children;
+        assert_eq!(children.len(), 2);
+        assert!(!children
+            .iter()
+            .any(|c| matches!(c.diff_type, DiffType::Additions | DiffType::Deletions)));
+        let moved: Vec<_> = children
+            .iter()
+            .filter(|c| c.diff_type == DiffType::Moved)
+            .collect();
+        assert_eq!(moved.len(), 1);
+        let a = children
+            .iter()
+            .find(|c| c.diff.right_value["name"] == "A")
+            .unwrap();
+        assert!(a.has_diff);
+    }
+
+    #[test]
+    fn keyed_sequence_composite_key() {
+        let options = DiffOptions {
+            sequence_keys: vec![SequenceKey::new("ports", &["port", "protocol"])],
+            ..Default::default()
+        };
+        let diffs = diff_with(
+            "ports:\n  - {port: 80, protocol: TCP, name: a}\n  - {port: 80, protocol: UDP, name: b}\n",
+            "ports:\n  - {port: 80, protocol: UDP, name: c}\n  - {port: 80, protocol: TCP, name: a}\n",
+            &options,
+        );
+        let children = &diffs[0].children;
+        assert_eq!(children.len(), 2);
+        let udp = children
+            .iter()
+            .find(|c| c.diff.left_value["protocol"] == "UDP")
+            .unwrap();
+        assert_eq!(udp.diff.right_value["name"], "c");
+        assert!(udp.children.iter().any(|c| c.has_diff));
+    }
+
+    #[test]
+    fn keyed_sequence_unkeyed_elements_compare_by_value() {
+        let options = DiffOptions {
+            sequence_keys: vec![SequenceKey::new("steps", &["name"])],
+            ..Default::default()
+        };
+        let diffs = diff_with(
+            "steps:\n  - uses: actions/checkout@v4\n  - name: build\n    run: make\n",
+            "steps:\n  - uses: actions/checkout@v5\n  - name: build\n    run: make\n",
+            &options,
+        );
+        assert_eq!(
+            summary(&diffs[0].children),
+            vec![
+                (Some("0".to_string()), DiffType::Modified),
+                (Some("1".to_string()), DiffType::Unchanged),
+            ]
+        );
+        let checkout = &diffs[0].children[0];
+        assert_eq!(
+            (checkout.left_index, checkout.right_index),
+            (Some(0), Some(0))
+        );
+        assert_eq!(checkout.children[0].key.as_deref(), Some("uses"));
+    }
+
+    #[test]
+    fn detected_key_reported_on_sequence_node() {
+        let diffs = diff_with(
+            "volumes:\n  - name: data\n    size: 1\n  - name: logs\n    size: 1\n",
+            "volumes:\n  - name: logs\n    size: 1\n  - name: data\n    size: 2\n",
+            &DiffOptions::default(),
+        );
+        assert_eq!(diffs[0].match_key.as_deref(), Some("name"));
+        let children = &diffs[0].children;
+        assert_eq!(children.len(), 2);
+        let data = children
+            .iter()
+            .find(|c| c.diff.left_value["name"] == "data")
+            .unwrap();
+        assert_eq!(data.diff.right_value["size"], 2);
+    }
+
+    #[test]
+    fn detection_skips_duplicate_values() {
+        let diffs = diff_with(
+            "items:\n  - name: a\n    id: 1\n  - name: a\n    id: 2\n",
+            "items:\n  - name: a\n    id: 2\n  - name: a\n    id: 1\n",
+            &DiffOptions::default(),
+        );
+        assert_eq!(diffs[0].match_key.as_deref(), Some("id"));
+    }
+
+    #[test]
+    fn detection_requires_all_mappings() {
+        let diffs = diff_with(
+            "items:\n  - name: a\n  - plain\n",
+            "items:\n  - name: b\n  - plain\n",
+            &DiffOptions::default(),
+        );
+        assert_eq!(diffs[0].match_key, None);
+    }
+
+    #[test]
+    fn detection_can_be_disabled() {
+        let options = DiffOptions {
+            detect_sequence_keys: false,
+            ..Default::default()
+        };
+        let diffs = diff_with(
+            "items:\n  - name: a\n  - name: b\n",
+            "items:\n  - name: b\n  - name: a\n",
+            &options,
+        );
+        assert_eq!(diffs[0].match_key, None);
+    }
+
+    #[test]
+    fn no_key_reported_when_matching_falls_back() {
+        let (left, right) = (
+            "items:\n  - name: a\n  - name: b\n",
+            "items:\n  - name: b\n  - name: a\n",
+        );
+        let unordered = DiffOptions {
+            ignore_sequence_order: true,
+            ..Default::default()
+        };
+        assert_eq!(diff_with(left, right, &unordered)[0].match_key, None);
+        let positional = DiffOptions {
+            seq_diff_product_limit: 1,
+            ..Default::default()
+        };
+        assert_eq!(diff_with(left, right, &positional)[0].match_key, None);
+    }
+
+    #[test]
+    fn equal_element_moved() {
+        let diffs = diff_with("[a, b, c, d]", "[b, c, d, a]", &DiffOptions::default());
+        let moved: Vec<_> = diffs
+            .iter()
+            .filter(|c| c.diff_type == DiffType::Moved)
+            .collect();
+        assert_eq!(moved.len(), 1);
+        assert_eq!(moved[0].diff.left_value, "a");
+        assert_eq!(
+            (moved[0].left_index, moved[0].right_index),
+            (Some(0), Some(3))
+        );
+        assert!(!moved[0].children.iter().any(|c| c.has_diff));
+        assert!(!diffs
+            .iter()
+            .any(|c| matches!(c.diff_type, DiffType::Additions | DiffType::Deletions)));
+    }
+
+    #[test]
+    fn nearly_equal_element_moved_and_diffed() {
+        let diffs = diff_with(
+            "- {host: a, port: 1, tls: true, weight: 5}\n- x\n- y\n- z\n",
+            "- x\n- y\n- z\n- {host: a, port: 2, tls: true, weight: 5}\n",
+            &DiffOptions {
+                detect_sequence_keys: false,
+                ..Default::default()
+            },
+        );
+        let moved: Vec<_> = diffs
+            .iter()
+            .filter(|c| c.diff_type == DiffType::Moved)
+            .collect();
+        assert_eq!(moved.len(), 1);
+        assert_eq!(
+            (moved[0].left_index, moved[0].right_index),
+            (Some(0), Some(3))
+        );
+        let port = moved[0]
+            .children
+            .iter()
+            .find(|c| c.key.as_deref() == Some("port"))
+            .unwrap();
+        assert_eq!(port.diff_type, DiffType::Modified);
+    }
+
+    #[test]
+    fn dissimilar_elements_not_moved() {
+        let diffs = diff_with("[a, x, y]", "[x, y, b]", &DiffOptions::default());
+        assert_eq!(
+            summary(&diffs),
+            vec![
+                (Some("0".to_string()), DiffType::Deletions),
+                (Some("1".to_string()), DiffType::Unchanged),
+                (Some("2".to_string()), DiffType::Unchanged),
+                (Some("3".to_string()), DiffType::Additions),
+            ]
+        );
+    }
+
+    #[test]
+    fn sequence_nodes_keyed_by_either_side() {
+        let keys = |sequence_index| {
+            let options = DiffOptions {
+                sequence_index,
+                ..Default::default()
+            };
+            diff_with("[a, b, c]", "[x, a, c]", &options)
+                .iter()
+                .map(|n| (n.key.clone().unwrap(), n.left_index, n.right_index))
+                .collect::<Vec<_>>()
+        };
+        let key = |k: &str, l, r| (k.to_string(), l, r);
+        assert_eq!(
+            keys(SequenceIndex::Merged),
+            vec![
+                key("0", None, Some(0)),
+                key("1", Some(0), Some(1)),
+                key("2", Some(1), None),
+                key("3", Some(2), Some(2)),
+            ]
+        );
+        let left: Vec<_> = keys(SequenceIndex::Left).into_iter().map(|k| k.0).collect();
+        assert_eq!(left, ["0", "0", "1", "2"]);
+        let right: Vec<_> = keys(SequenceIndex::Right)
+            .into_iter()
+            .map(|k| k.0)
+            .collect();
+        assert_eq!(right, ["0", "1", "1", "2"]);
+    }
+
+    #[test]
+    fn unordered_sequence_ignores_reordering() {
+        let options = DiffOptions {
+            ignore_sequence_order: true,
+            ..Default::default()
+        };
+        let diffs = diff_with("[a, b, c]", "[c, a, d]", &options);
+        assert_eq!(
+            summary(&diffs),
+            vec![
+                (Some("0".to_string()), DiffType::Unchanged),
+                (Some("1".to_string()), DiffType::Deletions),
+                (Some("2".to_string()), DiffType::Unchanged),
+                (Some("3".to_string()), DiffType::Additions),
+            ]
+        );
+    }
+
+    #[test]
+    fn unordered_sequence_counts_duplicates() {
+        let options = DiffOptions {
+            ignore_sequence_order: true,
+            ..Default::default()
+        };
+        let diffs = diff_with("[a, a, b]", "[b, a]", &options);
+        assert_eq!(
+            summary(&diffs),
+            vec![
+                (Some("0".to_string()), DiffType::Unchanged),
+                (Some("1".to_string()), DiffType::Deletions),
+                (Some("2".to_string()), DiffType::Unchanged),
+            ]
+        );
+    }
+
+    #[test]
+    fn unordered_sequence_by_path() {
+        let options = DiffOptions {
+            unordered_sequences: vec![PathPattern::parse("cidrs")],
+            ..Default::default()
+        };
+        let diffs = diff_with(
+            "cidrs: [10.0.0.0/8, 192.168.0.0/16]\nports: [80, 443]\n",
+            "cidrs: [192.168.0.0/16, 10.0.0.0/8]\nports: [443, 80]\n",
+            &options,
+        );
+        assert!(!diffs[0].has_diff);
+        assert!(diffs[1].has_diff);
+    }
+
+    #[test]
+    fn ignored_paths_are_left_out() {
+        let options = DiffOptions {
+            ignore_paths: vec![
+                PathPattern::parse("status"),
+                PathPattern::parse("**.generation"),
+                PathPattern::parse("metadata.annotations.kubectl*"),
+            ],
+            ..Default::default()
+        };
+        let diffs = diff_with(
+            "metadata:\n  name: web\n  generation: 1\n  annotations:\n    kubectl.kubernetes.io/last-applied-configuration: a\nstatus:\n  ready: false\n",
+            "metadata:\n  name: web\n  generation: 2\n  annotations:\n    kubectl.kubernetes.io/last-applied-configuration: b\nstatus:\n  ready: true\nspec: {}\n",
+            &options,
+        );
+        let keys: Vec<_> = diffs.iter().map(|d| d.key.as_deref()).collect();
+        assert_eq!(keys, vec![Some("metadata"), Some("spec")]);
+        let metadata = &diffs[0];
+        assert!(!metadata.has_diff);
+        assert_eq!(metadata.diff_type, DiffType::Unchanged);
+        let metadata_keys: Vec<_> = metadata.children.iter().map(|d| d.key.as_deref()).collect();
+        assert_eq!(metadata_keys, vec![Some("name"), Some("annotations")]);
+    }
+
+    #[test]
+    fn every_node_records_its_full_path() {
+        let diffs = diff_with(
+            "labels:\n  app.kubernetes.io/name: web\nports: [80]\n",
+            "labels:\n  app.kubernetes.io/name: api\nports: [80, 443]\n",
+            &DiffOptions::default(),
+        );
+        let label = &diffs[0].children[0];
+        assert_eq!(
+            label.path,
+            vec![
+                PathSegment::Key("labels".to_string()),
+                PathSegment::Key("app.kubernetes.io/name".to_string()),
+            ]
+        );
+        assert_eq!(label.pointer, "/labels/app.kubernetes.io~1name");
+        assert_eq!(label.dotted_path, r#".labels["app.kubernetes.io/name"]"#);
+        // The bare scalar under a key shares its path.
+        assert_eq!(label.children[0].pointer, label.pointer);
+
+        let added = &diffs[1].children[1];
+        assert_eq!(added.path[1], PathSegment::Index(1));
+        assert_eq!(
+            (added.pointer.as_str(), added.dotted_path.as_str()),
+            ("/ports/1", ".ports[1]")
+        );
+    }
+
+    #[test]
+    fn report_round_trips_with_string_diff_types() {
+        let left: serde_yml::Value = serde_yml::from_str("a: 1\nb: [x]").unwrap();
+        let right: serde_yml::Value = serde_yml::from_str("a: 2\nb: [x, y]").unwrap();
+        let diffs = yaml_diff(&left, &right, &DiffContext::new(&DiffOptions::default())).unwrap();
+        let text = serde_yml::to_string(&DiffReport::new(diffs.clone())).unwrap();
+        assert!(text.starts_with("format_version: 1\n"), "{text}");
+        assert!(text.contains("diff_type: modified"), "{text}");
+
+        let report: DiffReport = serde_yml::from_str(&text).unwrap();
+        assert_eq!(
+            serde_yml::to_string(&report.diffs).unwrap(),
+            serde_yml::to_string(&diffs).unwrap()
+        );
+    }
+
+    #[test]
+    fn report_from_a_newer_format_is_rejected() {
+        let err = serde_yml::from_str::<DiffReport>("format_version: 2\ndiffs: []")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("unsupported format_version 2"), "{err}");
+        assert!(serde_yml::from_str::<DiffReport>("diffs: []").is_err());
+    }
 }
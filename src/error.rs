@@ -0,0 +1,41 @@
+use std::fmt;
+
+/// Why a diff, or a patch or merge built on one, could not be computed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiffError {
+    /// The documents nest deeper than `DiffOptions::max_depth`.
+    MaxDepthExceeded { max_depth: usize },
+    /// A merge patch would have to set the members at these JSON Pointers
+    /// to `null`, which RFC 7396 can only read as deleting them.
+    NullInMergePatch { paths: Vec<String> },
+    /// The `<<` merge key at this JSON Pointer holds something other than
+    /// a mapping or a sequence of mappings.
+    InvalidMergeKey { path: String },
+    /// `DiffOptions` that deserialized fine but make no sense, e.g. a
+    /// `max_depth` of 0.
+    InvalidOptions { reason: String },
+}
+
+impl fmt::Display for DiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffError::MaxDepthExceeded { max_depth } => {
+                write!(f, "Maximum nesting depth exceeded ({max_depth} levels)")
+            }
+            DiffError::NullInMergePatch { paths } => {
+                write!(
+                    f,
+                    "Merge patch cannot set null values, at: {}",
+                    paths.join(", ")
+                )
+            }
+            DiffError::InvalidMergeKey { path } => write!(
+                f,
+                "Merge key at {path} must hold a mapping or a sequence of mappings"
+            ),
+            DiffError::InvalidOptions { reason } => write!(f, "Invalid options: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for DiffError {}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use crate::diff::{added_node, deleted_node, paired_node, unwrap_tagged, DiffContext, YamlDiff};
+use crate::error::DiffError;
+use crate::merge_keys::prepare_documents;
+use crate::options::DiffOptions;
+
+/// Identity of a Kubernetes resource: `apiVersion/kind/namespace/name`,
+/// with the namespace segment omitted for cluster-scoped resources.
+/// Returns `None` for documents that are not resource manifests.
+pub(crate) fn resource_identity(document: &serde_yml::Value) -> Option<String> {
+    let field = |value: &serde_yml::Value, name: &str| -> Option<String> {
+        match unwrap_tagged(value.get(name)?) {
+            serde_yml::Value::String(s) if !s.is_empty() => Some(s.clone()),
+            _ => None,
+        }
+    };
+
+    let api_version = field(document, "apiVersion")?;
+    let kind = field(document, "kind")?;
+    let metadata = document.get("metadata")?;
+    let name = field(metadata, "name")?;
+    match field(metadata, "namespace") {
+        Some(namespace) => Some(format!("{api_version}/{kind}/{namespace}/{name}")),
+        None => Some(format!("{api_version}/{kind}/{name}")),
+    }
+}
+
+/// Documents of one stream, split into those keyed by a unique resource
+/// identity and the rest.
+#[derive(Default)]
+struct DocumentIndex {
+    identified: Vec<(String, usize)>,
+    by_identity: HashMap<String, usize>,
+    unidentified: Vec<usize>,
+}
+
+/// A repeated identity only keys its first occurrence; later copies are
+/// treated like documents without an identity.
+fn index_documents(documents: &[serde_yml::Value]) -> DocumentIndex {
+    let mut index = DocumentIndex::default();
+
+    for (i, document) in documents.iter().enumerate() {
+        match resource_identity(document) {
+            Some(id) if !index.by_identity.contains_key(&id) => {
+                index.by_identity.insert(id.clone(), i);
+                index.identified.push((id, i));
+            }
+            _ => index.unidentified.push(i),
+        }
+    }
+
+    index
+}
+
+/// Diff two multi-document streams, pairing Kubernetes resources by identity
+/// instead of by position so reordered manifests still line up. Resources
+/// only present on one side are reported whole as additions or deletions.
+/// Documents without an identity fall back to positional pairing among
+/// themselves and are keyed by their document index.
+pub fn kubernetes_diff(
+    left: &[serde_yml::Value],
+    right: &[serde_yml::Value],
+    options: &DiffOptions,
+) -> Result<Vec<YamlDiff>, DiffError> {
+    let (left, right) = (
+        &*prepare_documents(left, options)?,
+        &*prepare_documents(right, options)?,
+    );
+    let document = DiffContext::new(options).document();
+    let left_index = index_documents(left);
+    let right_index = index_documents(right);
+
+    let mut diffs: Vec<YamlDiff> = Vec::new();
+
+    for (id, li) in &left_index.identified {
+        let key = Some(id.clone());
+        match right_index.by_identity.get(id) {
+            Some(&ri) => diffs
+                .push(paired_node(key, &left[*li], &right[ri], &document)?.at(Some(*li), Some(ri))),
+            None => diffs.push(deleted_node(key, &left[*li], &document).at(Some(*li), None)),
+        }
+    }
+
+    for (id, ri) in &right_index.identified {
+        if !left_index.by_identity.contains_key(id) {
+            diffs.push(added_node(Some(id.clone()), &right[*ri], &document).at(None, Some(*ri)));
+        }
+    }
+
+    let (left_rest, right_rest) = (&left_index.unidentified, &right_index.unidentified);
+    let max_len = std::cmp::max(left_rest.len(), right_rest.len());
+    for i in 0..max_len {
+        match (left_rest.get(i), right_rest.get(i)) {
+            (Some(&li), Some(&ri)) => diffs.push(
+                paired_node(Some(li.to_string()), &left[li], &right[ri], &document)?
+                    .at(Some(li), Some(ri)),
+            ),
+            (Some(&li), None) => diffs
+                .push(deleted_node(Some(li.to_string()), &left[li], &document).at(Some(li), None)),
+            (None, Some(&ri)) => diffs
+                .push(added_node(Some(ri.to_string()), &right[ri], &document).at(None, Some(ri))),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    Ok(diffs.into_iter().map(YamlDiff::in_document).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::DiffType;
+
+    fn docs(input: &str) -> Vec<serde_yml::Value> {
+        use serde::Deserialize;
+        serde_yml::Deserializer::from_str(input)
+            .map(|d| serde_yml::Value::deserialize(d).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn identity_includes_namespace_when_present() {
+        let doc = &docs(
+            "apiVersion: apps/v1\nkind: Deployment\nmetadata:\n  name: web\n  namespace: prod\n",
+        )[0];
+        assert_eq!(
+            resource_identity(doc).as_deref(),
+            Some("apps/v1/Deployment/prod/web")
+        );
+    }
+
+    #[test]
+    fn identity_cluster_scoped() {
+        let doc = &docs("apiVersion: v1\nkind: Namespace\nmetadata:\n  name: prod\n")[0];
+        assert_eq!(resource_identity(doc).as_deref(), Some("v1/Namespace/prod"));
+    }
+
+    #[test]
+    fn identity_missing_fields() {
+        let doc = &docs("kind: ConfigMap\nmetadata:\n  name: cfg\n")[0];
+        assert_eq!(resource_identity(doc), None);
+    }
+
+    #[test]
+    fn reordered_resources_pair_by_identity() {
+        let left = docs(
+            "apiVersion: v1\nkind: Service\nmetadata:\n  name: web\n---\n\
+             apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: cfg\ndata:\n  a: '1'\n",
+        );
+        let right = docs(
+            "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: cfg\ndata:\n  a: '2'\n---\n\
+             apiVersion: v1\nkind: Service\nmetadata:\n  name: web\n",
+        );
+        let diffs = kubernetes_diff(&left, &right, &DiffOptions::default()).unwrap();
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].key.as_deref(), Some("v1/Service/web"));
+        assert_eq!(diffs[0].diff_type, DiffType::Unchanged);
+        assert_eq!(diffs[1].key.as_deref(), Some("v1/ConfigMap/cfg"));
+        assert_eq!(diffs[1].diff_type, DiffType::Modified);
+    }
+
+    #[test]
+    fn added_and_removed_resources_are_whole() {
+        let left = docs("apiVersion: v1\nkind: Secret\nmetadata:\n  name: old\n");
+        let right = docs("apiVersion: v1\nkind: Secret\nmetadata:\n  name: new\n");
+        let diffs = kubernetes_diff(&left, &right, &DiffOptions::default()).unwrap();
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].key.as_deref(), Some("v1/Secret/old"));
+        assert_eq!(diffs[0].diff_type, DiffType::Deletions);
+        assert_eq!(diffs[1].key.as_deref(), Some("v1/Secret/new"));
+        assert_eq!(diffs[1].diff_type, DiffType::Additions);
+    }
+}
@@ -1,65 +1,86 @@
+pub mod apply;
+mod cloudformation;
 mod diff;
-
-use diff::{diff_vec_to_js, yaml_diff};
-use wasm_bindgen::prelude::*;
-
-fn read_yaml(data: &str) -> Result<serde_yml::Value, serde_yml::Error> {
-    let parsed_data: serde_yml::Value = serde_yml::from_str(data)?;
-    Ok(parsed_data)
-}
-
-fn format_parse_error(label: &str, e: &serde_yml::Error) -> String {
-    match e.location() {
-        Some(loc) => format!("[{label}] Error: {e} at line: {}", loc.line()),
-        None => format!("[{label}] Error: {e}"),
-    }
+pub mod error;
+mod kubernetes;
+pub mod merge;
+mod merge_keys;
+pub mod options;
+pub mod patch;
+pub mod path;
+pub mod source;
+mod text;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+pub use diff::{DiffReport, DiffType, DiffValue, YamlDiff, FORMAT_VERSION};
+pub use error::DiffError;
+pub use merge_keys::expand_merge_keys;
+pub use text::{InlineChanges, LineChange, LineHunk};
+
+use cloudformation::annotate_templates;
+use diff::{documents_diff, yaml_diff, DiffContext};
+use kubernetes::kubernetes_diff;
+use merge_keys::prepare_documents;
+use options::DiffOptions;
+use serde::Deserialize;
+
+/// Parse every `---` separated document in the input. An input holding
+/// only comments or directives yields a single null document so callers
+/// always have something to compare.
+pub fn read_yaml(data: &str) -> Result<Vec<serde_yml::Value>, serde_yml::Error> {
+    let mut documents = Vec::new();
+    for document in serde_yml::Deserializer::from_str(data) {
+        documents.push(serde_yml::Value::deserialize(document)?);
+    }
+    if documents.is_empty() {
+        documents.push(serde_yml::Value::Null);
+    }
+    Ok(documents)
 }
 
-fn validate_and_parse(
-    yone: &str,
-    ytwo: &str,
-) -> Result<(serde_yml::Value, serde_yml::Value), JsError> {
-    let yone_trimmed = yone.trim();
-    let ytwo_trimmed = ytwo.trim();
-
-    if yone_trimmed.is_empty() && ytwo_trimmed.is_empty() {
-        return Err(JsError::new(
-            "[YAML ONE] Error: empty input\n[YAML TWO] Error: empty input",
-        ));
-    }
-    if yone_trimmed.is_empty() {
-        return Err(JsError::new("[YAML ONE] Error: empty input"));
-    }
-    if ytwo_trimmed.is_empty() {
-        return Err(JsError::new("[YAML TWO] Error: empty input"));
-    }
-
-    let parsed_one = read_yaml(yone);
-    let parsed_two = read_yaml(ytwo);
-
-    match (parsed_one, parsed_two) {
-        (Ok(one), Ok(two)) => Ok((one, two)),
-        (Err(e1), Err(e2)) => {
-            let msg = format!(
-                "{}\n{}",
-                format_parse_error("YAML ONE", &e1),
-                format_parse_error("YAML TWO", &e2)
-            );
-            Err(JsError::new(&msg))
-        }
-        (Err(e), Ok(_)) => Err(JsError::new(&format_parse_error("YAML ONE", &e))),
-        (Ok(_), Err(e)) => Err(JsError::new(&format_parse_error("YAML TWO", &e))),
-    }
+/// Diff two YAML values, e.g. two parsed documents. The result holds one
+/// node per mapping member or sequence element of the root, or a single
+/// node when the roots are scalars or of different kinds.
+pub fn diff_values(
+    left: &serde_yml::Value,
+    right: &serde_yml::Value,
+    options: &DiffOptions,
+) -> Result<Vec<YamlDiff>, DiffError> {
+    let left = prepare_documents(std::slice::from_ref(left), options)?;
+    let right = prepare_documents(std::slice::from_ref(right), options)?;
+    let mut diffs = yaml_diff(&left[0], &right[0], &DiffContext::new(options))?;
+    if options.cloudformation {
+        annotate_templates(&mut diffs);
+    }
+    Ok(diffs)
 }
 
-/// Compute a complete diff of two YAML strings in one call.
-/// Handles all top-level types: mappings, sequences, scalars, and mixed.
-#[wasm_bindgen]
-pub fn compute_diff(yone: &str, ytwo: &str) -> Result<JsValue, JsError> {
-    let (one, two) = validate_and_parse(yone, ytwo)?;
-    let diffs =
-        yaml_diff(&one, &two, 0).map_err(|e| JsError::new(&format!("Diff error: {e:?}")))?;
-    diff_vec_to_js(&diffs).map_err(|e| JsError::new(&format!("Serialization error: {e:?}")))
+/// Diff two parsed YAML streams, pairing Kubernetes resources by identity
+/// when `options.kubernetes` is set and annotating CloudFormation templates
+/// when `options.cloudformation` is. This is what
+/// `compute_diff_with_options` runs in the browser.
+///
+/// The shape of the result depends on the streams. Two single-document
+/// streams, or one document against an empty stream, give the flat list
+/// `diff_values` would, with no node for the document itself. Any other
+/// pair gives one top-level node per document, keyed by its index, whose
+/// subtree records it in `YamlDiff::document`. With `options.kubernetes`
+/// every resource gets such a node, keyed by its identity.
+pub fn diff_documents(
+    one: &[serde_yml::Value],
+    two: &[serde_yml::Value],
+    options: &DiffOptions,
+) -> Result<Vec<YamlDiff>, DiffError> {
+    let mut diffs = if options.kubernetes {
+        kubernetes_diff(one, two, options)?
+    } else {
+        documents_diff(one, two, options)?
+    };
+    if options.cloudformation {
+        annotate_templates(&mut diffs);
+    }
+    Ok(diffs)
 }
 
 #[cfg(test)]
@@ -96,12 +117,25 @@ mod tests {
     #[test]
     fn read_yaml_valid() {
         let result = read_yaml("a: 1\nb: 2").unwrap();
-        insta::assert_snapshot!(serde_yml::to_string(&result).unwrap());
+        assert_eq!(result.len(), 1);
+        insta::assert_snapshot!(serde_yml::to_string(&result[0]).unwrap());
+    }
+
+    #[test]
+    fn read_yaml_multi_document() {
+        let result = read_yaml("a: 1\n---\nb: 2\n---\n- c\n").unwrap();
+        assert_eq!(result.len(), 3);
+    }
+
+    #[test]
+    fn read_yaml_comment_only() {
+        let result = read_yaml("# nothing here\n").unwrap();
+        assert_eq!(result, vec![serde_yml::Value::Null]);
     }
 
     fn compute_diff_test(yone: &str, ytwo: &str) -> Vec<diff::YamlDiff> {
-        let (one, two) = validate_and_parse(yone, ytwo).unwrap();
-        diff::yaml_diff(&one, &two, 0).unwrap()
+        let (one, two) = (read_yaml(yone).unwrap(), read_yaml(ytwo).unwrap());
+        diff::documents_diff(&one, &two, &DiffOptions::default()).unwrap()
     }
 
     #[test]
@@ -145,4 +179,29 @@ mod tests {
         let diffs = compute_diff_test("1", "hi:\n  - a\n  - b\n  - c\n");
         insta::assert_yaml_snapshot!(diffs);
     }
+
+    #[test]
+    fn e2e_multi_document() {
+        let diffs = compute_diff_test(
+            "kind: A\nv: 1\n---\nkind: B\n",
+            "kind: A\nv: 2\n---\nkind: B\n---\nkind: C\n",
+        );
+        insta::assert_yaml_snapshot!(diffs);
+    }
+
+    #[test]
+    fn e2e_multi_document_removed() {
+        let diffs = compute_diff_test("a: 1\n---\nb: 2\n", "a: 1\n");
+        insta::assert_yaml_snapshot!(diffs);
+    }
+
+    #[test]
+    fn e2e_created_and_deleted_documents() {
+        let documents = read_yaml("a: 1\nb: [x]\n").unwrap();
+        let created = diff::documents_diff(&[], &documents, &DiffOptions::default()).unwrap();
+        insta::assert_yaml_snapshot!(created);
+        let scalar = read_yaml("hello").unwrap();
+        let deleted = diff::documents_diff(&scalar, &[], &DiffOptions::default()).unwrap();
+        insta::assert_yaml_snapshot!(deleted);
+    }
 }
@@ -0,0 +1,956 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use crate::diff::{
+    element_identity, sequence_match_fields, serialize_value, tag_of, unwrap_tagged,
+    unwrap_tagged_mut, with_tag, yaml_diff, yaml_key_to_string, DiffContext, DiffType, YamlDiff,
+};
+use crate::error::DiffError;
+use crate::options::DiffOptions;
+use crate::path::PathSegment;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConflictKind {
+    /// Both sides changed the value, differently.
+    ModifyModify,
+    /// One side deleted what the other changed.
+    DeleteModify,
+    /// Both sides added the same key, or the same keyed element, with
+    /// different values, or different elements at the same place in a
+    /// sequence.
+    AddAdd,
+}
+
+impl fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConflictKind::ModifyModify => "modify/modify",
+            ConflictKind::DeleteModify => "delete/modify",
+            ConflictKind::AddAdd => "add/add",
+        })
+    }
+}
+
+/// Changes from both sides that could not be combined.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    /// Where the conflicting value sits in the merged document.
+    pub path: Vec<PathSegment>,
+    pub kind: ConflictKind,
+    /// The value in each version, `None` where it is absent.
+    pub base: Option<serde_yml::Value>,
+    pub ours: Option<serde_yml::Value>,
+    pub theirs: Option<serde_yml::Value>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MergeResult {
+    /// Both sides' changes combined, holding our version wherever they
+    /// conflict.
+    pub merged: serde_yml::Value,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl MergeResult {
+    /// The merged document as YAML with every conflict written out between
+    /// git-style markers `marker_size` characters long, our version above
+    /// the `=` line and theirs below. An absent version leaves its half
+    /// empty.
+    pub fn to_yaml_with_markers(&self, marker_size: usize) -> Result<String, serde_yml::Error> {
+        // Stand a unique placeholder scalar in for each conflict, then
+        // swap the lines holding one for the marked versions.
+        let plain = serde_yml::to_string(&self.merged)?;
+        let mut stem = "__yamalyze_conflict_".to_string();
+        while plain.contains(&stem) {
+            stem.push('_');
+        }
+        let mut document = self.merged.clone();
+        let mut conflicts: Vec<_> = self.conflicts.iter().enumerate().collect();
+        // Later and deeper paths first, so no placeholder shifts the
+        // position of one still to be placed.
+        conflicts.sort_by(|(_, a), (_, b)| b.path.cmp(&a.path));
+        for (i, conflict) in conflicts {
+            let placeholder = serde_yml::Value::String(format!("{stem}{i}__"));
+            place(
+                &mut document,
+                &conflict.path,
+                placeholder,
+                conflict.ours.is_some(),
+            );
+        }
+
+        let mut out = String::new();
+        for line in serde_yml::to_string(&document)?.lines() {
+            let found = line.find(&stem).and_then(|start| {
+                let digits = &line[start + stem.len()..];
+                let end = digits.find("__")?;
+                let conflict = self.conflicts.get(digits[..end].parse::<usize>().ok()?)?;
+                Some((&line[..start], conflict))
+            });
+            let Some((prefix, conflict)) = found else {
+                out.push_str(line);
+                out.push('\n');
+                continue;
+            };
+            out.push_str(&format!("{} ours\n", "<".repeat(marker_size)));
+            if let Some(ours) = &conflict.ours {
+                out.push_str(&render_at(prefix, ours)?);
+            }
+            out.push_str(&format!("{}\n", "=".repeat(marker_size)));
+            if let Some(theirs) = &conflict.theirs {
+                out.push_str(&render_at(prefix, theirs)?);
+            }
+            out.push_str(&format!("{} theirs\n", ">".repeat(marker_size)));
+        }
+        Ok(out)
+    }
+}
+
+/// Put `value` at `path`, replacing what is there when `replace` is set
+/// and inserting it otherwise.
+fn place(
+    document: &mut serde_yml::Value,
+    path: &[PathSegment],
+    value: serde_yml::Value,
+    replace: bool,
+) {
+    let Some((last, parents)) = path.split_last() else {
+        *document = value;
+        return;
+    };
+    let mut node = document;
+    for segment in parents {
+        let child = match (unwrap_tagged_mut(node), segment) {
+            (serde_yml::Value::Mapping(map), PathSegment::Key(name)) => map
+                .iter_mut()
+                .find(|(k, _)| yaml_key_to_string(k) == *name)
+                .map(|(_, v)| v),
+            (serde_yml::Value::Sequence(seq), PathSegment::Index(i)) => seq.get_mut(*i),
+            _ => None,
+        };
+        match child {
+            Some(child) => node = child,
+            None => return,
+        }
+    }
+    match (unwrap_tagged_mut(node), last) {
+        (serde_yml::Value::Mapping(map), PathSegment::Key(name)) => {
+            let existing = map.iter_mut().find(|(k, _)| yaml_key_to_string(k) == *name);
+            match existing {
+                Some((_, slot)) if replace => *slot = value,
+                _ => {
+                    map.insert(serde_yml::Value::String(name.clone()), value);
+                }
+            }
+        }
+        (serde_yml::Value::Sequence(seq), PathSegment::Index(i)) => {
+            if replace && *i < seq.len() {
+                seq[*i] = value;
+            } else {
+                seq.insert((*i).min(seq.len()), value);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Render `value` in place of a scalar that followed `prefix` on its
+/// line, e.g. `  key: ` or `  - `.
+fn render_at(prefix: &str, value: &serde_yml::Value) -> Result<String, serde_yml::Error> {
+    let text = serde_yml::to_string(value)?;
+    let lines: Vec<&str> = text.lines().collect();
+    let block = match unwrap_tagged(value) {
+        serde_yml::Value::Mapping(map) => !map.is_empty(),
+        serde_yml::Value::Sequence(seq) => !seq.is_empty(),
+        _ => false,
+    };
+    if let (false, [line]) = (block, lines.as_slice()) {
+        return Ok(format!("{prefix}{line}\n"));
+    }
+    // Column of the innermost node the prefix opens: past the indent and
+    // any sequence dashes.
+    let mut column = prefix.len() - prefix.trim_start_matches(' ').len();
+    while prefix[column..].starts_with("- ") {
+        column += 2;
+    }
+    let mut out = String::new();
+    if prefix.len() == column {
+        // Root or sequence item: the value starts on the same line.
+        for (i, line) in lines.iter().enumerate() {
+            let indent = if i == 0 {
+                prefix.to_string()
+            } else {
+                " ".repeat(column)
+            };
+            out.push_str(&format!("{indent}{line}\n"));
+        }
+    } else {
+        // Mapping member: the value goes on the lines below the key.
+        out.push_str(&format!("{}\n", prefix.trim_end()));
+        for line in lines {
+            out.push_str(&format!("{}{line}\n", " ".repeat(column + 2)));
+        }
+    }
+    Ok(out)
+}
+
+/// Three-way merge: diff `ours` and `theirs` against `base` and combine
+/// the changes. Mappings merge key by key and sequences element by
+/// element, matched the same way `yaml_diff` matches them, so edits to
+/// different keys or elements never conflict. Changes at paths matched by
+/// `options.ignore_paths` are merged like any other.
+pub fn three_way_merge(
+    base: &serde_yml::Value,
+    ours: &serde_yml::Value,
+    theirs: &serde_yml::Value,
+    options: &DiffOptions,
+) -> Result<MergeResult, DiffError> {
+    let mut merge = Merge {
+        conflicts: Vec::new(),
+    };
+    let merged = merge.values(base, ours, theirs, &DiffContext::new(options))?;
+    Ok(MergeResult {
+        merged,
+        conflicts: merge.conflicts,
+    })
+}
+
+/// Whether the content of a sequence element changed, as opposed to only
+/// its position. Compares the values rather than the children, which
+/// leave out ignored paths.
+fn edited(node: &YamlDiff) -> bool {
+    node.diff.left_value != node.diff.right_value || node.diff.left_tag != node.diff.right_tag
+}
+
+/// `(ours, theirs)` from a value of the leading and the other side.
+fn sides<T>(ours_lead: bool, lead: T, other: T) -> (T, T) {
+    if ours_lead {
+        (lead, other)
+    } else {
+        (other, lead)
+    }
+}
+
+fn empty_mapping() -> serde_yml::Value {
+    serde_yml::Value::Mapping(serde_yml::Mapping::new())
+}
+
+/// What one side did to a mapping member. A changed member has no node
+/// when ignore patterns hide it from the diff.
+enum Member<'a> {
+    Kept(&'a serde_yml::Value),
+    Changed(&'a serde_yml::Value, Option<&'a YamlDiff>),
+    Removed,
+    Added(&'a serde_yml::Value),
+    Missing,
+}
+
+impl<'a> Member<'a> {
+    fn new(
+        base: Option<&serde_yml::Value>,
+        side: Option<&'a serde_yml::Value>,
+        node: Option<&'a YamlDiff>,
+    ) -> Self {
+        match (base, side) {
+            (Some(base), Some(value)) => match node {
+                Some(node) if node.has_diff => Member::Changed(value, Some(node)),
+                None if value != base => Member::Changed(value, None),
+                _ => Member::Kept(value),
+            },
+            (Some(_), None) => Member::Removed,
+            (None, Some(value)) => Member::Added(value),
+            (None, None) => Member::Missing,
+        }
+    }
+}
+
+/// An element of the other side woven into the leading side's sequence,
+/// with its base index when the leading side deleted it.
+type Woven<'a> = (Option<usize>, &'a serde_yml::Value);
+
+/// One side's changes to a sequence, read off its diff against the base.
+struct SequenceEdit<'a> {
+    /// The node for every base element, by base index.
+    by_base: Vec<&'a YamlDiff>,
+    /// The node for every element of this side, in order.
+    order: Vec<&'a YamlDiff>,
+    reordered: bool,
+}
+
+impl<'a> SequenceEdit<'a> {
+    /// `None` when some element has no node, which happens when ignore
+    /// patterns hide sequence elements.
+    fn read(base_len: usize, side_len: usize, diffs: &'a [YamlDiff]) -> Option<Self> {
+        let mut by_base = vec![None; base_len];
+        let mut order = vec![None; side_len];
+        for node in diffs {
+            if let Some(li) = node.left_index {
+                *by_base.get_mut(li)? = Some(node);
+            }
+            if let Some(ri) = node.right_index {
+                *order.get_mut(ri)? = Some(node);
+            }
+        }
+        Some(Self {
+            by_base: by_base.into_iter().collect::<Option<_>>()?,
+            order: order.into_iter().collect::<Option<_>>()?,
+            reordered: diffs.iter().any(|d| d.diff_type == DiffType::Moved),
+        })
+    }
+}
+
+struct Merge {
+    conflicts: Vec<Conflict>,
+}
+
+impl Merge {
+    fn conflict(
+        &mut self,
+        ctx: &DiffContext,
+        kind: ConflictKind,
+        base: Option<&serde_yml::Value>,
+        ours: Option<&serde_yml::Value>,
+        theirs: Option<&serde_yml::Value>,
+    ) {
+        self.conflicts.push(Conflict {
+            path: ctx.path.clone(),
+            kind,
+            base: base.cloned(),
+            ours: ours.cloned(),
+            theirs: theirs.cloned(),
+        });
+    }
+
+    fn values(
+        &mut self,
+        base: &serde_yml::Value,
+        ours: &serde_yml::Value,
+        theirs: &serde_yml::Value,
+        ctx: &DiffContext,
+    ) -> Result<serde_yml::Value, DiffError> {
+        let ours_diff = yaml_diff(base, ours, ctx)?;
+        let theirs_diff = yaml_diff(base, theirs, ctx)?;
+        self.nodes(base, ours, theirs, &ours_diff, &theirs_diff, ctx)
+    }
+
+    /// Merge three versions of a value given each side's diff against the
+    /// base.
+    fn nodes(
+        &mut self,
+        base: &serde_yml::Value,
+        ours: &serde_yml::Value,
+        theirs: &serde_yml::Value,
+        ours_diff: &[YamlDiff],
+        theirs_diff: &[YamlDiff],
+        ctx: &DiffContext,
+    ) -> Result<serde_yml::Value, DiffError> {
+        // Compare the values rather than the diffs, which leave out
+        // ignored paths: ignore patterns narrow the diff, not the merge.
+        if ours == base {
+            return Ok(theirs.clone());
+        }
+        if theirs == base || ours == theirs {
+            return Ok(ours.clone());
+        }
+        let merged = match (
+            unwrap_tagged(base),
+            unwrap_tagged(ours),
+            unwrap_tagged(theirs),
+        ) {
+            (
+                serde_yml::Value::Mapping(b),
+                serde_yml::Value::Mapping(o),
+                serde_yml::Value::Mapping(t),
+            ) => serde_yml::Value::Mapping(self.mappings(b, o, t, ours_diff, theirs_diff, ctx)?),
+            (
+                serde_yml::Value::Sequence(b),
+                serde_yml::Value::Sequence(o),
+                serde_yml::Value::Sequence(t),
+            ) => self.sequences(b, o, t, ours_diff, theirs_diff, ctx)?,
+            _ => {
+                self.conflict(
+                    ctx,
+                    ConflictKind::ModifyModify,
+                    Some(base),
+                    Some(ours),
+                    Some(theirs),
+                );
+                return Ok(ours.clone());
+            }
+        };
+        Ok(with_tag(&merged, &self.tag(base, ours, theirs, ctx)))
+    }
+
+    /// The tag of a merged collection: the one a side changed it to, or
+    /// ours when both changed it differently, which is a conflict.
+    fn tag(
+        &mut self,
+        base: &serde_yml::Value,
+        ours: &serde_yml::Value,
+        theirs: &serde_yml::Value,
+        ctx: &DiffContext,
+    ) -> Option<String> {
+        let (b, o, t) = (tag_of(base), tag_of(ours), tag_of(theirs));
+        if o == b {
+            return t;
+        }
+        if t != b && t != o {
+            self.conflict(
+                ctx,
+                ConflictKind::ModifyModify,
+                Some(base),
+                Some(ours),
+                Some(theirs),
+            );
+        }
+        o
+    }
+
+    /// Both sides added a value at the same place.
+    fn added_twice(
+        &mut self,
+        ours: &serde_yml::Value,
+        theirs: &serde_yml::Value,
+        ctx: &DiffContext,
+    ) -> Result<serde_yml::Value, DiffError> {
+        if ours == theirs {
+            return Ok(ours.clone());
+        }
+        if unwrap_tagged(ours).is_mapping() && unwrap_tagged(theirs).is_mapping() {
+            return self.values(&empty_mapping(), ours, theirs, ctx);
+        }
+        self.conflict(ctx, ConflictKind::AddAdd, None, Some(ours), Some(theirs));
+        Ok(ours.clone())
+    }
+
+    fn mappings(
+        &mut self,
+        base: &serde_yml::Mapping,
+        ours: &serde_yml::Mapping,
+        theirs: &serde_yml::Mapping,
+        ours_diff: &[YamlDiff],
+        theirs_diff: &[YamlDiff],
+        ctx: &DiffContext,
+    ) -> Result<serde_yml::Mapping, DiffError> {
+        let by_key = |diffs| -> HashMap<&str, &YamlDiff> {
+            <&[YamlDiff]>::into_iter(diffs)
+                .filter_map(|d| d.key.as_deref().map(|k| (k, d)))
+                .collect()
+        };
+        let (ours_nodes, theirs_nodes) = (by_key(ours_diff), by_key(theirs_diff));
+
+        // Base keys in order, then what we added, then what they added.
+        let mut keys: Vec<&serde_yml::Value> = base.keys().collect();
+        keys.extend(ours.keys().filter(|k| !base.contains_key(k)));
+        keys.extend(
+            theirs
+                .keys()
+                .filter(|k| !base.contains_key(k) && !ours.contains_key(k)),
+        );
+
+        let mut merged = serde_yml::Mapping::new();
+        for key in keys {
+            let name = yaml_key_to_string(key);
+            let child = ctx.child(PathSegment::Key(name.clone()));
+            let b = base.get(key);
+            let o = Member::new(b, ours.get(key), ours_nodes.get(name.as_str()).copied());
+            let t = Member::new(b, theirs.get(key), theirs_nodes.get(name.as_str()).copied());
+
+            let value = match (o, t) {
+                (Member::Kept(o), Member::Kept(_)) => Some(o.clone()),
+                (Member::Kept(_), Member::Changed(t, _)) => Some(t.clone()),
+                (Member::Changed(o, _), Member::Kept(_)) => Some(o.clone()),
+                (Member::Changed(o, Some(on)), Member::Changed(t, Some(tn))) => {
+                    let b = b.unwrap_or(&serde_yml::Value::Null);
+                    Some(self.nodes(b, o, t, &on.children, &tn.children, &child)?)
+                }
+                (Member::Changed(o, _), Member::Changed(t, _)) => {
+                    Some(self.values(b.unwrap_or(&serde_yml::Value::Null), o, t, &child)?)
+                }
+                (Member::Removed, Member::Changed(t, _)) => {
+                    self.conflict(&child, ConflictKind::DeleteModify, b, None, Some(t));
+                    None
+                }
+                (Member::Changed(o, _), Member::Removed) => {
+                    self.conflict(&child, ConflictKind::DeleteModify, b, Some(o), None);
+                    Some(o.clone())
+                }
+                (Member::Added(o), Member::Added(t)) => Some(self.added_twice(o, t, &child)?),
+                (Member::Added(o), _) => Some(o.clone()),
+                (_, Member::Added(t)) => Some(t.clone()),
+                _ => None,
+            };
+            if let Some(value) = value {
+                merged.insert(key.clone(), value);
+            }
+        }
+
+        Ok(merged)
+    }
+
+    /// Walk one side's version of the sequence, the skeleton, and weave in
+    /// the other side's insertions, each after the nearest element before
+    /// it that both sides kept. The skeleton is ours unless only they
+    /// reordered elements. Different elements both sides inserted at the
+    /// same place are an `add/add` conflict, unless they are matched by
+    /// identity.
+    fn sequences(
+        &mut self,
+        base: &serde_yml::Sequence,
+        ours: &serde_yml::Sequence,
+        theirs: &serde_yml::Sequence,
+        ours_diff: &[YamlDiff],
+        theirs_diff: &[YamlDiff],
+        ctx: &DiffContext,
+    ) -> Result<serde_yml::Value, DiffError> {
+        let edits = SequenceEdit::read(base.len(), ours.len(), ours_diff).zip(SequenceEdit::read(
+            base.len(),
+            theirs.len(),
+            theirs_diff,
+        ));
+        let Some((ours_edit, theirs_edit)) = edits else {
+            self.conflict(
+                ctx,
+                ConflictKind::ModifyModify,
+                Some(&serde_yml::Value::Sequence(base.clone())),
+                Some(&serde_yml::Value::Sequence(ours.clone())),
+                Some(&serde_yml::Value::Sequence(theirs.clone())),
+            );
+            return Ok(serde_yml::Value::Sequence(ours.clone()));
+        };
+
+        let ours_lead = !theirs_edit.reordered || ours_edit.reordered;
+        let ((lead, lead_seq), (other, other_seq)) = if ours_lead {
+            ((ours_edit, ours), (theirs_edit, theirs))
+        } else {
+            ((theirs_edit, theirs), (ours_edit, ours))
+        };
+
+        // Pair up elements both sides inserted, by identity.
+        let fields = sequence_match_fields(ours, theirs, ctx);
+        let identity = |value: &serde_yml::Value| match &fields {
+            Some(fields) => element_identity(value, fields),
+            None => serialize_value(value),
+        };
+        let mut lead_inserts: HashMap<String, VecDeque<usize>> = HashMap::new();
+        for node in &lead.order {
+            if let (None, Some(ri)) = (node.left_index, node.right_index) {
+                lead_inserts
+                    .entry(identity(&lead_seq[ri]))
+                    .or_default()
+                    .push_back(ri);
+            }
+        }
+
+        // The other side's elements to weave in, keyed by the base index
+        // they follow. Elements the leading side deleted but the other
+        // edited carry their base index.
+        let mut woven: HashMap<Option<usize>, Vec<Woven>> = HashMap::new();
+        let mut twins: HashMap<usize, &serde_yml::Value> = HashMap::new();
+        let mut anchor: Option<usize> = None;
+        for node in &other.order {
+            let Some(ri) = node.right_index else { continue };
+            let value = &other_seq[ri];
+            match node.left_index {
+                None => {
+                    let twin = lead_inserts
+                        .get_mut(&identity(value))
+                        .and_then(|queue| queue.pop_front());
+                    match twin {
+                        Some(lead_ri) => {
+                            twins.insert(lead_ri, value);
+                        }
+                        None => woven.entry(anchor).or_default().push((None, value)),
+                    }
+                }
+                Some(li) if lead.by_base[li].right_index.is_some() => anchor = Some(li),
+                Some(li) if edited(node) => {
+                    woven.entry(anchor).or_default().push((Some(li), value));
+                }
+                Some(_) => {}
+            }
+        }
+
+        // Elements both sides inserted at the same place conflict, each
+        // side's first with the other's first and so on, unless they are
+        // matched by identity: then nothing orders them, and the other
+        // side's come first.
+        let mut clashes: HashMap<usize, &serde_yml::Value> = HashMap::new();
+        if fields.is_none() {
+            let mut anchor: Option<usize> = None;
+            for node in &lead.order {
+                match (node.left_index, node.right_index) {
+                    (Some(li), _) if other.by_base[li].right_index.is_some() => anchor = Some(li),
+                    (None, Some(ri)) if !twins.contains_key(&ri) => {
+                        let Some(inserted) = woven.get_mut(&anchor) else {
+                            continue;
+                        };
+                        if let Some(at) = inserted.iter().position(|(li, _)| li.is_none()) {
+                            clashes.insert(ri, inserted.remove(at).1);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut merged: serde_yml::Sequence = Vec::new();
+        let no_woven = Vec::new();
+        let weave = |merge: &mut Self, merged: &mut serde_yml::Sequence, after| {
+            for &(deleted, value) in woven.get(&after).unwrap_or(&no_woven) {
+                let Some(li) = deleted else {
+                    merged.push(value.clone());
+                    continue;
+                };
+                let (o, t) = sides(ours_lead, None, Some(value));
+                let child = ctx.child(PathSegment::Index(merged.len()));
+                merge.conflict(&child, ConflictKind::DeleteModify, Some(&base[li]), o, t);
+                // Keep our edit when it was they who deleted.
+                if !ours_lead {
+                    merged.push(value.clone());
+                }
+            }
+        };
+        weave(self, &mut merged, None);
+
+        for node in &lead.order {
+            let Some(ri) = node.right_index else { continue };
+            let value = &lead_seq[ri];
+            let child = ctx.child(PathSegment::Index(merged.len()));
+            let Some(li) = node.left_index else {
+                let element = match (twins.get(&ri), clashes.get(&ri)) {
+                    (Some(twin), _) => {
+                        let (o, t) = sides(ours_lead, value, *twin);
+                        self.added_twice(o, t, &child)?
+                    }
+                    (None, Some(clash)) => {
+                        let (o, t) = sides(ours_lead, value, *clash);
+                        self.conflict(&child, ConflictKind::AddAdd, None, Some(o), Some(t));
+                        o.clone()
+                    }
+                    (None, None) => value.clone(),
+                };
+                merged.push(element);
+                continue;
+            };
+
+            let other_node = other.by_base[li];
+            let Some(other_ri) = other_node.right_index else {
+                if edited(node) {
+                    let (o, t) = sides(ours_lead, Some(value), None);
+                    self.conflict(&child, ConflictKind::DeleteModify, Some(&base[li]), o, t);
+                    // Keep our edit when it was they who deleted.
+                    if ours_lead {
+                        merged.push(value.clone());
+                    }
+                }
+                continue;
+            };
+
+            let other_value = &other_seq[other_ri];
+            let element = match (edited(node), edited(other_node)) {
+                (_, false) => value.clone(),
+                (false, true) => other_value.clone(),
+                (true, true) => {
+                    let (o, t) = sides(ours_lead, value, other_value);
+                    let (on, tn) = sides(ours_lead, *node, other_node);
+                    self.nodes(&base[li], o, t, &on.children, &tn.children, &child)?
+                }
+            };
+            merged.push(element);
+            weave(self, &mut merged, Some(li));
+        }
+
+        Ok(serde_yml::Value::Sequence(merged))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path::json_pointer;
+
+    fn yaml(input: &str) -> serde_yml::Value {
+        serde_yml::from_str(input).unwrap()
+    }
+
+    fn merge(base: &str, ours: &str, theirs: &str) -> MergeResult {
+        three_way_merge(
+            &yaml(base),
+            &yaml(ours),
+            &yaml(theirs),
+            &DiffOptions::default(),
+        )
+        .unwrap()
+    }
+
+    fn conflicts(result: &MergeResult) -> Vec<(String, ConflictKind)> {
+        result
+            .conflicts
+            .iter()
+            .map(|c| (json_pointer(&c.path), c.kind))
+            .collect()
+    }
+
+    #[test]
+    fn independent_mapping_changes_combine() {
+        let result = merge(
+            "a: 1\nb: 1\nc: 1\nd: {x: 1}\n",
+            "a: 2\nb: 1\nc: 1\nd: {x: 1, y: 2}\n",
+            "a: 1\nc: 1\nd: {x: 3}\ne: 5\n",
+        );
+        assert!(result.conflicts.is_empty());
+        assert_eq!(result.merged, yaml("a: 2\nc: 1\nd: {x: 3, y: 2}\ne: 5\n"));
+    }
+
+    #[test]
+    fn same_change_on_both_sides_is_not_a_conflict() {
+        let result = merge("a: 1", "a: 2\nb: [1]", "a: 2\nb: [1]");
+        assert!(result.conflicts.is_empty());
+        assert_eq!(result.merged, yaml("a: 2\nb: [1]"));
+    }
+
+    #[test]
+    fn modify_modify_keeps_ours() {
+        let result = merge(
+            "a: {b: 1}\nc: 1\n",
+            "a: {b: 2}\nc: 1\n",
+            "a: {b: 3}\nc: 2\n",
+        );
+        assert_eq!(
+            result.conflicts,
+            vec![Conflict {
+                path: vec![
+                    PathSegment::Key("a".to_string()),
+                    PathSegment::Key("b".to_string())
+                ],
+                kind: ConflictKind::ModifyModify,
+                base: Some(yaml("1")),
+                ours: Some(yaml("2")),
+                theirs: Some(yaml("3")),
+            }]
+        );
+        assert_eq!(result.merged, yaml("a: {b: 2}\nc: 2\n"));
+    }
+
+    #[test]
+    fn delete_modify_in_both_directions() {
+        let result = merge("a: 1\nb: 1\n", "b: 2\n", "a: 2\n");
+        assert_eq!(
+            conflicts(&result),
+            vec![
+                ("/a".to_string(), ConflictKind::DeleteModify),
+                ("/b".to_string(), ConflictKind::DeleteModify),
+            ]
+        );
+        assert_eq!(result.conflicts[0].ours, None);
+        assert_eq!(result.conflicts[1].theirs, None);
+        assert_eq!(result.merged, yaml("b: 2\n"));
+    }
+
+    #[test]
+    fn add_add_conflicts_only_where_values_differ() {
+        let result = merge(
+            "{}",
+            "x: 1\nnew: {same: 1, port: 80}\n",
+            "x: 2\nnew: {same: 1, port: 81}\n",
+        );
+        assert_eq!(
+            conflicts(&result),
+            vec![
+                ("/x".to_string(), ConflictKind::AddAdd),
+                ("/new/port".to_string(), ConflictKind::AddAdd),
+            ]
+        );
+        assert_eq!(result.conflicts[0].base, None);
+        assert_eq!(result.merged, yaml("x: 1\nnew: {same: 1, port: 80}\n"));
+    }
+
+    #[test]
+    fn sequence_insertions_from_both_sides() {
+        let result = merge("[a, b, c]", "[a, b, c, d]", "[x, a, b, c]");
+        assert!(result.conflicts.is_empty());
+        assert_eq!(result.merged, yaml("[x, a, b, c, d]"));
+
+        let result = merge("[a, b, c]", "[a, b, y, c]", "[a, b, y, c]");
+        assert_eq!(result.merged, yaml("[a, b, y, c]"));
+    }
+
+    #[test]
+    fn insertions_at_the_same_place_conflict() {
+        let result = merge("[a, b]", "[a, x, b]", "[a, y, b]");
+        assert_eq!(
+            conflicts(&result),
+            vec![("/1".to_string(), ConflictKind::AddAdd)]
+        );
+        assert_eq!(result.conflicts[0].theirs, Some(yaml("y")));
+        assert_eq!(result.merged, yaml("[a, x, b]"));
+
+        let result = merge("[a]", "[a, x, z]", "[a, y]");
+        assert_eq!(
+            conflicts(&result),
+            vec![("/1".to_string(), ConflictKind::AddAdd)]
+        );
+        assert_eq!(result.merged, yaml("[a, x, z]"));
+
+        let result = merge(
+            "- {name: a}\n- {name: b}\n",
+            "- {name: a}\n- {name: x}\n- {name: b}\n",
+            "- {name: a}\n- {name: y}\n- {name: b}\n",
+        );
+        assert!(result.conflicts.is_empty());
+        assert_eq!(
+            result.merged,
+            yaml("- {name: a}\n- {name: y}\n- {name: x}\n- {name: b}\n")
+        );
+    }
+
+    #[test]
+    fn sequence_delete_modify() {
+        let result = merge("[a, b, c]", "[a, c]", "[a, B, c]");
+        assert_eq!(
+            conflicts(&result),
+            vec![("/1".to_string(), ConflictKind::DeleteModify)]
+        );
+        assert_eq!(result.merged, yaml("[a, c]"));
+    }
+
+    #[test]
+    fn keyed_elements_merge_field_by_field() {
+        let base = "containers:\n  - {name: web, image: v1}\n  - {name: side, image: s1}\n";
+        let result = merge(
+            base,
+            "containers:\n  - {name: web, image: v2}\n  - {name: side, image: s1}\n",
+            "containers:\n  - {name: web, image: v1}\n  - {name: side, image: s2}\n  - {name: log, image: l1}\n",
+        );
+        assert!(result.conflicts.is_empty());
+        assert_eq!(
+            result.merged,
+            yaml("containers:\n  - {name: web, image: v2}\n  - {name: side, image: s2}\n  - {name: log, image: l1}\n")
+        );
+
+        let result = merge(
+            base,
+            "containers:\n  - {name: web, image: v1}\n  - {name: side, image: s1}\n  - {name: log, image: l1}\n",
+            "containers:\n  - {name: web, image: v1}\n  - {name: side, image: s1}\n  - {name: log, image: l2}\n",
+        );
+        assert_eq!(
+            conflicts(&result),
+            vec![("/containers/2/image".to_string(), ConflictKind::AddAdd)]
+        );
+    }
+
+    #[test]
+    fn their_reordering_is_kept_with_our_edit() {
+        let result = merge(
+            "- {name: a, v: 1}\n- {name: b, v: 1}\n- {name: c, v: 1}\n",
+            "- {name: a, v: 2}\n- {name: b, v: 1}\n- {name: c, v: 1}\n",
+            "- {name: c, v: 1}\n- {name: a, v: 1}\n- {name: b, v: 1}\n",
+        );
+        assert!(result.conflicts.is_empty());
+        assert_eq!(
+            result.merged,
+            yaml("- {name: c, v: 1}\n- {name: a, v: 2}\n- {name: b, v: 1}\n")
+        );
+    }
+
+    #[test]
+    fn conflicts_are_written_between_markers() {
+        let result = merge(
+            "a: {b: 1}\nlist: [x, y]\nc: 1\n",
+            "a: {b: 2}\nlist: [x]\nc: 1\nd: {e: 1, f: 2}\n",
+            "a: {b: 3}\nlist: [x, Y]\nd: [9]\n",
+        );
+        assert_eq!(
+            result.to_yaml_with_markers(7).unwrap(),
+            "a:\n\
+             <<<<<<< ours\n\
+             \x20 b: 2\n\
+             =======\n\
+             \x20 b: 3\n\
+             >>>>>>> theirs\n\
+             list:\n\
+             - x\n\
+             <<<<<<< ours\n\
+             =======\n\
+             - 'Y'\n\
+             >>>>>>> theirs\n\
+             <<<<<<< ours\n\
+             d:\n\
+             \x20 e: 1\n\
+             \x20 f: 2\n\
+             =======\n\
+             d:\n\
+             \x20 - 9\n\
+             >>>>>>> theirs\n"
+        );
+        assert!(merge("a: 1", "a: 2", "a: 1")
+            .to_yaml_with_markers(7)
+            .unwrap()
+            .starts_with("a: 2"));
+    }
+
+    #[test]
+    fn ignored_paths_still_merge() {
+        let options = DiffOptions {
+            ignore_paths: vec![crate::path::PathPattern::parse("**.status")],
+            ..Default::default()
+        };
+        let merged = |base: &str, ours: &str, theirs: &str| {
+            three_way_merge(&yaml(base), &yaml(ours), &yaml(theirs), &options).unwrap()
+        };
+
+        let result = merged("status: 1\n", "status: 1\na: 2\n", "status: 2\n");
+        assert!(result.conflicts.is_empty());
+        assert_eq!(result.merged, yaml("status: 2\na: 2\n"));
+
+        let result = merged(
+            "- {name: a, status: 1}\n- {name: b}\n",
+            "- {name: a, status: 1}\n- {name: b, v: 1}\n",
+            "- {name: a, status: 2}\n- {name: b}\n",
+        );
+        assert_eq!(
+            result.merged,
+            yaml("- {name: a, status: 2}\n- {name: b, v: 1}\n")
+        );
+
+        let result = merged("status: 1\n", "status: 2\na: 1\n", "status: 3\n");
+        assert_eq!(
+            conflicts(&result),
+            vec![("/status".to_string(), ConflictKind::ModifyModify)]
+        );
+    }
+
+    #[test]
+    fn their_retag_is_kept_with_our_edit() {
+        let result = merge("x: {a: 1}\n", "x: {a: 1, b: 2}\n", "x: !Foo {a: 1}\n");
+        assert!(result.conflicts.is_empty());
+        assert_eq!(result.merged, yaml("x: !Foo {a: 1, b: 2}\n"));
+
+        let result = merge("[1]", "[1, 2]", "!Set [1]");
+        assert_eq!(result.merged, yaml("!Set [1, 2]"));
+
+        let result = merge("x: {a: 1}\n", "x: !A {a: 1}\n", "x: !B {a: 1}\n");
+        assert_eq!(
+            conflicts(&result),
+            vec![("/x".to_string(), ConflictKind::ModifyModify)]
+        );
+        assert_eq!(result.merged, yaml("x: !A {a: 1}\n"));
+
+        let result = merge("{}", "x: !A 1\n", "x: !B 1\n");
+        assert_eq!(
+            conflicts(&result),
+            vec![("/x".to_string(), ConflictKind::AddAdd)]
+        );
+    }
+
+    #[test]
+    fn scalar_roots() {
+        let result = merge("1", "2", "3");
+        assert_eq!(
+            conflicts(&result),
+            vec![(String::new(), ConflictKind::ModifyModify)]
+        );
+        assert_eq!(merge("1", "1", "3").merged, yaml("3"));
+    }
+}
@@ -0,0 +1,172 @@
+use std::borrow::Cow;
+
+use crate::cloudformation::normalize_intrinsics;
+use crate::diff::{unwrap_tagged, unwrap_tagged_mut, yaml_key_to_string};
+use crate::error::DiffError;
+use crate::options::DiffOptions;
+use crate::path::{json_pointer, PathSegment};
+
+/// The key that merges other mappings into its own, from YAML 1.1's
+/// <https://yaml.org/type/merge.html>.
+pub(crate) const MERGE_KEY: &str = "<<";
+
+/// Replace every `<<` entry with the members it merges in, following YAML
+/// 1.1: keys written in the mapping itself win over merged ones, and when
+/// `<<` holds a sequence of mappings, earlier mappings win over later ones.
+/// Merged members take the place of the `<<` entry, and mappings that are
+/// merged in have their own `<<` entries expanded first.
+pub fn expand_merge_keys(value: &mut serde_yml::Value) -> Result<(), DiffError> {
+    expand(value, &mut Vec::new())
+}
+
+fn expand(value: &mut serde_yml::Value, path: &mut Vec<PathSegment>) -> Result<(), DiffError> {
+    match unwrap_tagged_mut(value) {
+        serde_yml::Value::Mapping(map) => {
+            for (key, member) in map.iter_mut() {
+                path.push(PathSegment::Key(yaml_key_to_string(key)));
+                expand(member, path)?;
+                path.pop();
+            }
+            if map.contains_key(MERGE_KEY) {
+                path.push(PathSegment::Key(MERGE_KEY.to_string()));
+                let merged = merged_members(map, path)?;
+                path.pop();
+                *map = merged;
+            }
+        }
+        serde_yml::Value::Sequence(seq) => {
+            for (i, element) in seq.iter_mut().enumerate() {
+                path.push(PathSegment::Index(i));
+                expand(element, path)?;
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// `map` with its `<<` entry, at `path`, replaced by the members it brings
+/// in that `map` doesn't set itself.
+fn merged_members(
+    map: &serde_yml::Mapping,
+    path: &[PathSegment],
+) -> Result<serde_yml::Mapping, DiffError> {
+    let invalid = || DiffError::InvalidMergeKey {
+        path: json_pointer(path),
+    };
+    let sources: Vec<&serde_yml::Mapping> = match unwrap_tagged(&map[MERGE_KEY]) {
+        serde_yml::Value::Mapping(source) => vec![source],
+        serde_yml::Value::Sequence(seq) => seq
+            .iter()
+            .map(|source| unwrap_tagged(source).as_mapping().ok_or_else(invalid))
+            .collect::<Result<_, _>>()?,
+        _ => return Err(invalid()),
+    };
+
+    let mut merged = serde_yml::Mapping::new();
+    for (key, value) in map {
+        if key != MERGE_KEY {
+            merged.insert(key.clone(), value.clone());
+            continue;
+        }
+        for (key, value) in sources.iter().flat_map(|source| source.iter()) {
+            if !map.contains_key(key) && !merged.contains_key(key) {
+                merged.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    Ok(merged)
+}
+
+/// The documents to diff: with merge keys expanded unless
+/// `options.raw_merge_keys` is set, and CloudFormation intrinsic functions
+/// in their long form when `options.cloudformation` is.
+pub(crate) fn prepare_documents<'a>(
+    documents: &'a [serde_yml::Value],
+    options: &DiffOptions,
+) -> Result<Cow<'a, [serde_yml::Value]>, DiffError> {
+    if options.raw_merge_keys && !options.cloudformation {
+        return Ok(Cow::Borrowed(documents));
+    }
+    let mut documents = documents.to_vec();
+    for document in &mut documents {
+        if !options.raw_merge_keys {
+            expand_merge_keys(document)?;
+        }
+        if options.cloudformation {
+            normalize_intrinsics(document);
+        }
+    }
+    Ok(Cow::Owned(documents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expanded(yaml: &str) -> Result<String, DiffError> {
+        let mut value: serde_yml::Value = serde_yml::from_str(yaml).unwrap();
+        expand_merge_keys(&mut value)?;
+        Ok(serde_yml::to_string(&value).unwrap())
+    }
+
+    #[test]
+    fn explicit_keys_override_merged_ones_in_place() {
+        assert_eq!(
+            expanded("base: &b {a: 1, b: 2}\nx:\n  first: 0\n  <<: *b\n  b: 3\n").unwrap(),
+            "base:\n  a: 1\n  b: 2\nx:\n  first: 0\n  a: 1\n  b: 3\n"
+        );
+    }
+
+    #[test]
+    fn earlier_mappings_in_a_sequence_win() {
+        assert_eq!(
+            expanded("- &a {x: 1}\n- &b {x: 2, z: 2}\n- <<: [*a, *b]\n").unwrap(),
+            "- x: 1\n- x: 2\n  z: 2\n- x: 1\n  z: 2\n"
+        );
+    }
+
+    #[test]
+    fn merged_mappings_are_expanded_first() {
+        assert_eq!(
+            expanded("a: &a {k: 1}\nb: &b {<<: *a, l: 2}\nc: {<<: *b}\n").unwrap(),
+            "a:\n  k: 1\nb:\n  k: 1\n  l: 2\nc:\n  k: 1\n  l: 2\n"
+        );
+    }
+
+    #[test]
+    fn diffs_see_the_effective_mapping_unless_raw() {
+        let left = crate::read_yaml("base: &b {image: a}\njob:\n  <<: *b\n").unwrap();
+        let right = crate::read_yaml("base: &b {image: b}\njob:\n  <<: *b\n").unwrap();
+        let changed = |options: &DiffOptions| -> Vec<String> {
+            let diffs = crate::diff_documents(&left, &right, options).unwrap();
+            let job = diffs
+                .iter()
+                .find(|d| d.key.as_deref() == Some("job"))
+                .unwrap();
+            job.children
+                .iter()
+                .filter(|d| d.has_diff)
+                .map(|d| d.pointer.clone())
+                .collect()
+        };
+        assert_eq!(changed(&DiffOptions::default()), ["/job/image"]);
+        let raw = DiffOptions {
+            raw_merge_keys: true,
+            ..Default::default()
+        };
+        assert_eq!(changed(&raw), ["/job/<<"]);
+    }
+
+    #[test]
+    fn merging_a_scalar_is_an_error() {
+        assert_eq!(
+            expanded("jobs:\n  - <<: 1\n"),
+            Err(DiffError::InvalidMergeKey {
+                path: "/jobs/0/<<".to_string()
+            })
+        );
+        assert!(expanded("a: {<<: [{x: 1}, 2]}").is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use serde::Deserialize;
+
+use crate::error::DiffError;
+use crate::path::{PathPattern, PathSegment};
+
+/// Nesting depth at which a diff gives up rather than risk the stack.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// When two sequences have a product of lengths exceeding this limit,
+/// fall back to positional comparison instead of Myers diff to avoid
+/// excessive memory/time on pathological inputs.
+pub const DEFAULT_SEQ_DIFF_PRODUCT_LIMIT: usize = 10_000_000;
+
+/// Match elements of the sequences at `path` by the values of `fields`
+/// instead of by position, e.g. Kubernetes containers by `name`.
+/// Several fields form a composite identity.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SequenceKey {
+    pub path: PathPattern,
+    pub fields: Vec<String>,
+}
+
+impl SequenceKey {
+    pub fn new(path: &str, fields: &[&str]) -> Self {
+        Self {
+            path: PathPattern::parse(path),
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+}
+
+/// Which index a sequence element's node is keyed by for display. Its
+/// `left_index` and `right_index` are recorded either way.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SequenceIndex {
+    /// Running position in the diff, where deletions and additions each
+    /// take a slot.
+    #[default]
+    Merged,
+    /// Index in the left sequence, or the right one for additions.
+    Left,
+    /// Index in the right sequence, or the left one for deletions.
+    Right,
+}
+
+/// How finely a changed single-line string is split into the changed
+/// parts reported as its `inline_changes`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InlineGranularity {
+    /// No inline changes.
+    None,
+    /// Words, broken at whitespace and at `/ : . - _ @ =`, so a changed
+    /// image tag or URL segment is marked on its own.
+    #[default]
+    Word,
+    /// Single characters.
+    Char,
+}
+
+/// Knobs that change how two documents are compared. Deserializes from
+/// the options object of `compute_diff_with_options`; missing fields keep
+/// their defaults and unknown ones are rejected.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiffOptions {
+    /// Identity fields for sequences of mappings, first match wins.
+    pub sequence_keys: Vec<SequenceKey>,
+    /// For sequences without a configured key, look for a `name`, `id` or
+    /// `key` field that identifies every element and match by it.
+    pub detect_sequence_keys: bool,
+    /// Compare every sequence as a multiset of values: reordering is not a
+    /// change, only elements missing from one side are reported.
+    pub ignore_sequence_order: bool,
+    /// Sequences compared as multisets even when `ignore_sequence_order` is
+    /// off, e.g. `spec.tolerations` or `*.allowedCidrs`.
+    pub unordered_sequences: Vec<PathPattern>,
+    /// Nodes left out of the diff entirely, e.g. `status`,
+    /// `metadata.resourceVersion` or `**.generation`.
+    pub ignore_paths: Vec<PathPattern>,
+    /// Pair documents of a multi-document stream as Kubernetes resources
+    /// by identity instead of by position.
+    pub kubernetes: bool,
+    /// Compare CloudFormation templates: short-form intrinsic functions
+    /// such as `!Ref X` are read as their long form `{Ref: X}`, and
+    /// resources whose `Type` changed are marked `replaced`.
+    pub cloudformation: bool,
+    /// Compare values without their YAML tags, so `!Ref a` and `!Sub a`
+    /// are equal, for inputs whose tags carry no meaning. Tags are still
+    /// recorded on each `DiffValue`.
+    pub strip_tags: bool,
+    /// Diff `<<` merge keys as the literal keys they are written as,
+    /// instead of expanding them into the members they merge in. Patches
+    /// and merges always see the documents as written; a diff that
+    /// `apply_diff` should replay must be made with this set when it
+    /// touches merged-in members.
+    pub raw_merge_keys: bool,
+    /// Index that keys sequence element nodes: `merged` (the default),
+    /// `left` or `right`.
+    pub sequence_index: SequenceIndex,
+    /// Granularity of the `inline_changes` of changed single-line strings:
+    /// `word` (the default), `char` or `none`.
+    pub inline_granularity: InlineGranularity,
+    /// Deepest nesting compared before the diff fails.
+    pub max_depth: usize,
+    /// Largest product of two sequence lengths aligned with Myers diff;
+    /// longer pairs are compared position by position. Also bounds the
+    /// line counts of multi-line strings given `line_hunks` and the word
+    /// or char counts of strings given `inline_changes`.
+    pub seq_diff_product_limit: usize,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            sequence_keys: Vec::new(),
+            detect_sequence_keys: true,
+            ignore_sequence_order: false,
+            unordered_sequences: Vec::new(),
+            ignore_paths: Vec::new(),
+            kubernetes: false,
+            cloudformation: false,
+            strip_tags: false,
+            raw_merge_keys: false,
+            sequence_index: SequenceIndex::Merged,
+            inline_granularity: InlineGranularity::Word,
+            max_depth: DEFAULT_MAX_DEPTH,
+            seq_diff_product_limit: DEFAULT_SEQ_DIFF_PRODUCT_LIMIT,
+        }
+    }
+}
+
+impl DiffOptions {
+    /// Reject values that deserialize fine but make no sense.
+    pub fn validate(&self) -> Result<(), DiffError> {
+        let invalid = |reason: String| Err(DiffError::InvalidOptions { reason });
+        if self.max_depth == 0 {
+            return invalid("max_depth must be at least 1".to_string());
+        }
+        for (i, key) in self.sequence_keys.iter().enumerate() {
+            if key.fields.is_empty() {
+                return invalid(format!("sequence_keys[{i}].fields must not be empty"));
+            }
+            if key.fields.iter().any(|f| f.is_empty()) {
+                return invalid(format!(
+                    "sequence_keys[{i}].fields must not contain empty names"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Identity fields configured for the sequence at `path`, if any.
+    pub(crate) fn sequence_key_for(&self, path: &[PathSegment]) -> Option<&[String]> {
+        self.sequence_keys
+            .iter()
+            .find(|k| k.path.matches(path))
+            .map(|k| k.fields.as_slice())
+    }
+
+    /// Whether the sequence at `path` is compared without regard to order.
+    pub(crate) fn is_unordered(&self, path: &[PathSegment]) -> bool {
+        self.ignore_sequence_order || self.unordered_sequences.iter().any(|p| p.matches(path))
+    }
+
+    /// Whether the node at `path` is excluded from the diff.
+    pub(crate) fn is_ignored(&self, path: &[PathSegment]) -> bool {
+        self.ignore_paths.iter().any(|p| p.matches(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<DiffOptions, String> {
+        let options: DiffOptions = serde_yml::from_str(input).map_err(|e| e.to_string())?;
+        options.validate().map_err(|e| e.to_string())?;
+        Ok(options)
+    }
+
+    #[test]
+    fn missing_fields_keep_defaults() {
+        let options = parse("ignore_paths: [status, '**.generation']").unwrap();
+        assert_eq!(options.ignore_paths.len(), 2);
+        assert!(options.detect_sequence_keys);
+        assert_eq!(options.max_depth, DEFAULT_MAX_DEPTH);
+        assert_eq!(
+            options.seq_diff_product_limit,
+            DEFAULT_SEQ_DIFF_PRODUCT_LIMIT
+        );
+    }
+
+    #[test]
+    fn sequence_keys_from_objects() {
+        let options =
+            parse("sequence_keys:\n  - path: spec.containers\n    fields: [name]\nmax_depth: 32\n")
+                .unwrap();
+        assert_eq!(options.sequence_keys[0].fields, vec!["name".to_string()]);
+        assert_eq!(options.max_depth, 32);
+    }
+
+    #[test]
+    fn sequence_index_by_name() {
+        assert_eq!(
+            parse("sequence_index: right").unwrap().sequence_index,
+            SequenceIndex::Right
+        );
+        assert!(parse("sequence_index: middle").is_err());
+    }
+
+    #[test]
+    fn inline_granularity_by_name() {
+        assert_eq!(
+            parse("inline_granularity: char")
+                .unwrap()
+                .inline_granularity,
+            InlineGranularity::Char
+        );
+        assert_eq!(
+            parse("{}").unwrap().inline_granularity,
+            InlineGranularity::Word
+        );
+        assert!(parse("inline_granularity: line").is_err());
+    }
+
+    #[test]
+    fn unknown_option_is_rejected() {
+        let err = parse("ignore_path: [status]").unwrap_err();
+        assert!(err.contains("unknown field `ignore_path`"), "{err}");
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert!(parse("max_depth: -1").is_err());
+        assert_eq!(
+            parse("max_depth: 0").unwrap_err(),
+            "Invalid options: max_depth must be at least 1"
+        );
+        let options = DiffOptions {
+            sequence_keys: vec![SequenceKey::new("items", &[])],
+            ..Default::default()
+        };
+        assert_eq!(
+            options.validate(),
+            Err(DiffError::InvalidOptions {
+                reason: "sequence_keys[0].fields must not be empty".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,597 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::diff::{
+    tag_of, unwrap_tagged, yaml_diff, yaml_key_to_string, DiffContext, DiffType, YamlDiff,
+};
+use crate::error::DiffError;
+use crate::options::DiffOptions;
+use crate::path::escape_pointer_token;
+
+/// One RFC 6902 JSON Patch operation. Paths are RFC 6901 JSON Pointers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add {
+        path: String,
+        value: serde_yml::Value,
+    },
+    Remove {
+        path: String,
+    },
+    Replace {
+        path: String,
+        value: serde_yml::Value,
+    },
+    Move {
+        from: String,
+        path: String,
+    },
+    Copy {
+        from: String,
+        path: String,
+    },
+    Test {
+        path: String,
+        value: serde_yml::Value,
+    },
+}
+
+/// Operations collected while walking a diff. A guarded patch precedes
+/// every operation that overwrites or removes a value with a `test` of
+/// what the diff saw there.
+struct Ops {
+    ops: Vec<PatchOperation>,
+    guarded: bool,
+}
+
+impl Ops {
+    fn push(&mut self, op: PatchOperation) {
+        self.ops.push(op);
+    }
+
+    fn expect(&mut self, path: &str, value: &serde_yml::Value) {
+        if self.guarded {
+            self.ops.push(PatchOperation::Test {
+                path: path.to_string(),
+                value: value.clone(),
+            });
+        }
+    }
+}
+
+/// Diff `left` against `right` and express the result as a JSON Patch that
+/// turns `left` into `right`.
+pub fn json_patch(
+    left: &serde_yml::Value,
+    right: &serde_yml::Value,
+    options: &DiffOptions,
+) -> Result<Vec<PatchOperation>, DiffError> {
+    let diffs = yaml_diff(left, right, &DiffContext::new(options))?;
+    Ok(diff_to_json_patch(left, right, &diffs))
+}
+
+/// Turn the output of `yaml_diff(left, right)` into JSON Patch operations.
+/// Ignored paths produce no operations, so the patch leaves them as they
+/// are on the left.
+pub(crate) fn diff_to_json_patch(
+    left: &serde_yml::Value,
+    right: &serde_yml::Value,
+    diffs: &[YamlDiff],
+) -> Vec<PatchOperation> {
+    let mut ops = Ops {
+        ops: Vec::new(),
+        guarded: false,
+    };
+    node_ops("", left, right, diffs, &mut ops);
+    ops.ops
+}
+
+/// Like `diff_to_json_patch`, with a `test` of the left value before each
+/// `remove`, `replace` and `move`, so applying the patch to a document
+/// that differs from `left` at those places fails instead of clobbering.
+pub(crate) fn diff_to_guarded_json_patch(
+    left: &serde_yml::Value,
+    right: &serde_yml::Value,
+    diffs: &[YamlDiff],
+) -> Vec<PatchOperation> {
+    let mut ops = Ops {
+        ops: Vec::new(),
+        guarded: true,
+    };
+    node_ops("", left, right, diffs, &mut ops);
+    ops.ops
+}
+
+fn node_ops(
+    pointer: &str,
+    left: &serde_yml::Value,
+    right: &serde_yml::Value,
+    children: &[YamlDiff],
+    ops: &mut Ops,
+) {
+    let retagged = tag_of(left) != tag_of(right);
+    match (unwrap_tagged(left), unwrap_tagged(right)) {
+        (serde_yml::Value::Mapping(_), serde_yml::Value::Mapping(_)) if !retagged => {
+            mapping_ops(pointer, children, ops)
+        }
+        (serde_yml::Value::Sequence(l), serde_yml::Value::Sequence(_)) if !retagged => {
+            sequence_ops(pointer, l.len(), children, ops)
+        }
+        // Scalars and type changes come back as one whole-value node; a
+        // collection whose tag changed is replaced whole, tag included.
+        _ => {
+            if retagged || children.iter().any(|c| c.has_diff) {
+                ops.expect(pointer, left);
+                ops.push(PatchOperation::Replace {
+                    path: pointer.to_string(),
+                    value: right.clone(),
+                });
+            }
+        }
+    }
+}
+
+fn mapping_ops(pointer: &str, children: &[YamlDiff], ops: &mut Ops) {
+    for child in children {
+        let Some(key) = &child.key else { continue };
+        let path = format!("{pointer}/{}", escape_pointer_token(key));
+        match child.diff_type {
+            DiffType::Deletions => {
+                ops.expect(&path, &child.diff.left_tagged());
+                ops.push(PatchOperation::Remove { path });
+            }
+            DiffType::Additions => ops.push(PatchOperation::Add {
+                path,
+                value: child.diff.right_tagged(),
+            }),
+            _ if child.has_diff => node_ops(
+                &path,
+                &child.diff.left_tagged(),
+                &child.diff.right_tagged(),
+                &child.children,
+                ops,
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// An element of a sequence being patched: one taken over from the left
+/// side, by left index, or one added by the patch, by right index.
+#[derive(Clone, Copy, PartialEq)]
+enum Slot {
+    Left(usize),
+    Added(usize),
+}
+
+fn position(current: &[Slot], slot: Slot) -> Option<usize> {
+    current.iter().position(|s| *s == slot)
+}
+
+/// Where an element goes so it directly follows the element placed before
+/// it in the right sequence.
+fn insertion_point(current: &[Slot], previous: Option<Slot>) -> usize {
+    previous
+        .and_then(|p| position(current, p))
+        .map_or(0, |pos| pos + 1)
+}
+
+/// Left indices of a longest run of kept elements, in sequence order,
+/// whose right indices increase. These stay where they are; every other
+/// kept element is moved around them.
+fn stable_elements(kept: &[(usize, usize)]) -> HashSet<usize> {
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; kept.len()];
+    for (i, &(_, ri)) in kept.iter().enumerate() {
+        let at = tails.partition_point(|&t| kept[t].1 < ri);
+        previous[i] = at.checked_sub(1).map(|a| tails[a]);
+        if at == tails.len() {
+            tails.push(i);
+        } else {
+            tails[at] = i;
+        }
+    }
+
+    let mut stable = HashSet::new();
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        stable.insert(kept[i].0);
+        next = previous[i];
+    }
+    stable
+}
+
+/// Remove deleted elements from the back, then walk the right sequence in
+/// order: elements outside the stable run are moved, new ones added, each
+/// right after its predecessor, and changed elements patched recursively.
+fn sequence_ops(pointer: &str, left_len: usize, children: &[YamlDiff], ops: &mut Ops) {
+    let mut current: Vec<Slot> = (0..left_len).map(Slot::Left).collect();
+
+    let mut removed: Vec<(usize, &YamlDiff)> = children
+        .iter()
+        .filter(|c| c.right_index.is_none())
+        .filter_map(|c| c.left_index.map(|li| (li, c)))
+        .collect();
+    removed.sort_unstable_by_key(|(li, _)| std::cmp::Reverse(*li));
+    for (li, child) in removed {
+        if let Some(pos) = position(&current, Slot::Left(li)) {
+            current.remove(pos);
+            let path = format!("{pointer}/{pos}");
+            ops.expect(&path, &child.diff.left_tagged());
+            ops.push(PatchOperation::Remove { path });
+        }
+    }
+
+    let mut placed: Vec<(usize, &YamlDiff)> = children
+        .iter()
+        .filter_map(|c| c.right_index.map(|ri| (ri, c)))
+        .collect();
+    placed.sort_by_key(|(ri, _)| *ri);
+
+    let right_of: HashMap<usize, usize> = placed
+        .iter()
+        .filter_map(|(ri, c)| c.left_index.map(|li| (li, *ri)))
+        .collect();
+    let kept: Vec<(usize, usize)> = current
+        .iter()
+        .filter_map(|slot| match slot {
+            Slot::Left(li) => right_of.get(li).map(|ri| (*li, *ri)),
+            Slot::Added(_) => None,
+        })
+        .collect();
+    let stable = stable_elements(&kept);
+
+    let mut previous: Option<Slot> = None;
+    for (ri, child) in placed {
+        let Some(li) = child.left_index else {
+            let to = insertion_point(&current, previous);
+            current.insert(to, Slot::Added(ri));
+            ops.push(PatchOperation::Add {
+                path: format!("{pointer}/{to}"),
+                value: child.diff.right_tagged(),
+            });
+            previous = Some(Slot::Added(ri));
+            continue;
+        };
+
+        let slot = Slot::Left(li);
+        let stays = stable.contains(&li);
+        // Unchanged elements that stay put need no lookup.
+        if stays && !child.has_diff {
+            previous = Some(slot);
+            continue;
+        }
+        let Some(from) = position(&current, slot) else {
+            continue;
+        };
+        let at = if stays {
+            from
+        } else {
+            current.remove(from);
+            let to = insertion_point(&current, previous);
+            current.insert(to, slot);
+            if from != to {
+                ops.expect(&format!("{pointer}/{from}"), &child.diff.left_tagged());
+                ops.push(PatchOperation::Move {
+                    from: format!("{pointer}/{from}"),
+                    path: format!("{pointer}/{to}"),
+                });
+            }
+            to
+        };
+        if child.has_diff {
+            node_ops(
+                &format!("{pointer}/{at}"),
+                &child.diff.left_tagged(),
+                &child.diff.right_tagged(),
+                &child.children,
+                ops,
+            );
+        }
+        previous = Some(slot);
+    }
+}
+
+/// Diff `left` against `right` and express the result as an RFC 7396 JSON
+/// Merge Patch: changed keys carry their new value, deleted keys `null`,
+/// and changed sequences are replaced whole. Fails, naming every affected
+/// path, when the right side holds a `null` inside a mapping, which a
+/// merge patch can only express as a deletion.
+pub fn merge_patch(
+    left: &serde_yml::Value,
+    right: &serde_yml::Value,
+    options: &DiffOptions,
+) -> Result<serde_yml::Value, DiffError> {
+    let diffs = yaml_diff(left, right, &DiffContext::new(options))?;
+    diff_to_merge_patch(left, right, &diffs).map_err(|paths| DiffError::NullInMergePatch { paths })
+}
+
+/// Turn the output of `yaml_diff(left, right)` into a merge patch, or the
+/// JSON Pointers of the nulls it cannot represent.
+pub(crate) fn diff_to_merge_patch(
+    left: &serde_yml::Value,
+    right: &serde_yml::Value,
+    diffs: &[YamlDiff],
+) -> Result<serde_yml::Value, Vec<String>> {
+    let mut unrepresentable = Vec::new();
+    let patch = merge_node("", left, right, diffs, &mut unrepresentable)
+        .unwrap_or_else(|| serde_yml::Value::Mapping(serde_yml::Mapping::new()));
+    if unrepresentable.is_empty() {
+        Ok(patch)
+    } else {
+        Err(unrepresentable)
+    }
+}
+
+/// Merge patch for one node, `None` when it is unchanged.
+fn merge_node(
+    pointer: &str,
+    left: &serde_yml::Value,
+    right: &serde_yml::Value,
+    children: &[YamlDiff],
+    unrepresentable: &mut Vec<String>,
+) -> Option<serde_yml::Value> {
+    let retagged = tag_of(left) != tag_of(right);
+    if !retagged && !children.iter().any(|c| c.has_diff) {
+        return None;
+    }
+    let (serde_yml::Value::Mapping(_), serde_yml::Value::Mapping(_), false) =
+        (unwrap_tagged(left), unwrap_tagged(right), retagged)
+    else {
+        // Anything but a mapping is replaced whole, sequences and retagged
+        // mappings included. A null root is fine: a non-mapping patch
+        // simply becomes the result.
+        if !(pointer.is_empty() && unwrap_tagged(right).is_null()) {
+            find_nulls(pointer, right, unrepresentable);
+        }
+        return Some(right.clone());
+    };
+
+    let mut patch = serde_yml::Mapping::new();
+    for child in children {
+        let Some(key) = &child.key else { continue };
+        let path = format!("{pointer}/{}", escape_pointer_token(key));
+        let value = match child.diff_type {
+            DiffType::Deletions => serde_yml::Value::Null,
+            DiffType::Additions => {
+                find_nulls(&path, &child.diff.right_value, unrepresentable);
+                child.diff.right_tagged()
+            }
+            _ => {
+                let Some(value) = merge_node(
+                    &path,
+                    &child.diff.left_tagged(),
+                    &child.diff.right_tagged(),
+                    &child.children,
+                    unrepresentable,
+                ) else {
+                    continue;
+                };
+                value
+            }
+        };
+        patch.insert(serde_yml::Value::String(key.clone()), value);
+    }
+    Some(serde_yml::Value::Mapping(patch))
+}
+
+/// Record every `null` that would be applied as a mapping member. Values
+/// inside sequences are copied verbatim and may be null.
+fn find_nulls(pointer: &str, value: &serde_yml::Value, unrepresentable: &mut Vec<String>) {
+    match unwrap_tagged(value) {
+        serde_yml::Value::Null => unrepresentable.push(pointer.to_string()),
+        serde_yml::Value::Mapping(map) => {
+            for (key, member) in map {
+                let path = format!(
+                    "{pointer}/{}",
+                    escape_pointer_token(&yaml_key_to_string(key))
+                );
+                find_nulls(&path, member, unrepresentable);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apply::apply_patch;
+    use crate::path::PathPattern;
+
+    fn round_trip(left: &str, right: &str, options: &DiffOptions) -> Vec<PatchOperation> {
+        let left: serde_yml::Value = serde_yml::from_str(left).unwrap();
+        let right: serde_yml::Value = serde_yml::from_str(right).unwrap();
+        let ops = json_patch(&left, &right, options).unwrap();
+        let patched = apply_patch(&left, &ops).unwrap();
+        assert_eq!(patched, right, "patch: {ops:?}");
+        ops
+    }
+
+    #[test]
+    fn mapping_round_trip() {
+        let ops = round_trip(
+            "a: 1\nb: {c: 2, d: 3}\nx/y: old\n",
+            "a: 1\nb: {c: 5, e: 4}\nx/y: new\n~k: added\n",
+            &DiffOptions::default(),
+        );
+        assert_eq!(
+            ops,
+            vec![
+                PatchOperation::Replace {
+                    path: "/b/c".to_string(),
+                    value: serde_yml::Value::from(5),
+                },
+                PatchOperation::Remove {
+                    path: "/b/d".to_string(),
+                },
+                PatchOperation::Add {
+                    path: "/b/e".to_string(),
+                    value: serde_yml::Value::from(4),
+                },
+                PatchOperation::Replace {
+                    path: "/x~1y".to_string(),
+                    value: serde_yml::Value::from("new"),
+                },
+                PatchOperation::Add {
+                    path: "/~0k".to_string(),
+                    value: serde_yml::Value::from("added"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn sequence_inserts_and_deletes_round_trip() {
+        round_trip("[a, b, c, d]", "[a, x, c, y, z]", &DiffOptions::default());
+        round_trip("[a, b, c]", "[]", &DiffOptions::default());
+        round_trip("[]", "[a, b]", &DiffOptions::default());
+        round_trip(
+            "items: [{name: a, v: 1}, {name: b, v: 2}]\n",
+            "items: [{name: c, v: 0}, {name: b, v: 3}]\n",
+            &DiffOptions::default(),
+        );
+    }
+
+    #[test]
+    fn moved_elements_use_move() {
+        let ops = round_trip(
+            "[alpha, beta, gamma, delta]",
+            "[beta, gamma, delta, alpha]",
+            &DiffOptions::default(),
+        );
+        assert_eq!(
+            ops,
+            vec![PatchOperation::Move {
+                from: "/0".to_string(),
+                path: "/3".to_string(),
+            }]
+        );
+        round_trip("[x, y, a]", "[a, y, x]", &DiffOptions::default());
+        round_trip(
+            "[{name: a, v: 1}, {name: b, v: 2}, {name: c, v: 3}]",
+            "[{name: c, v: 4}, {name: a, v: 1}, {name: b, v: 2}]",
+            &DiffOptions::default(),
+        );
+    }
+
+    #[test]
+    fn unordered_sequences_round_trip() {
+        let options = DiffOptions {
+            unordered_sequences: vec![PathPattern::parse("tags")],
+            ..Default::default()
+        };
+        round_trip("tags: [a, b, b, c]\n", "tags: [c, b, d, a]\n", &options);
+    }
+
+    #[test]
+    fn tags_round_trip() {
+        let ops = round_trip("a: !Ref X\n", "a: !Sub X\n", &DiffOptions::default());
+        assert_eq!(
+            ops,
+            vec![PatchOperation::Replace {
+                path: "/a".to_string(),
+                value: serde_yml::from_str("!Sub X").unwrap(),
+            }]
+        );
+        round_trip(
+            "a: !If [c, x]\n",
+            "a: !Or [c, x]\n",
+            &DiffOptions::default(),
+        );
+        round_trip(
+            "a: !If [c, x]\nl: [!Ref a]\n",
+            "a: !If [c, y]\nl: [!Ref a, !GetAtt b.c]\nm: !Sub z\n",
+            &DiffOptions::default(),
+        );
+    }
+
+    #[test]
+    fn scalar_roots_round_trip() {
+        let ops = round_trip("hello", "world", &DiffOptions::default());
+        assert_eq!(
+            ops,
+            vec![PatchOperation::Replace {
+                path: String::new(),
+                value: serde_yml::Value::from("world"),
+            }]
+        );
+        assert!(round_trip("1", "1", &DiffOptions::default()).is_empty());
+        round_trip("a: 1", "[a]", &DiffOptions::default());
+        round_trip("a: [1, 2]", "a: {b: 1}", &DiffOptions::default());
+    }
+
+    /// RFC 7396 `MergePatch(Target, Patch)`.
+    fn apply_merge(target: &mut serde_yml::Value, patch: &serde_yml::Value) {
+        let serde_yml::Value::Mapping(patch) = patch else {
+            *target = patch.clone();
+            return;
+        };
+        if !target.is_mapping() {
+            *target = serde_yml::Value::Mapping(serde_yml::Mapping::new());
+        }
+        let serde_yml::Value::Mapping(map) = target else {
+            unreachable!()
+        };
+        for (key, value) in patch {
+            if value.is_null() {
+                map.remove(key);
+            } else {
+                let member = map.entry(key.clone()).or_insert(serde_yml::Value::Null);
+                apply_merge(member, value);
+            }
+        }
+    }
+
+    fn merge_round_trip(left: &str, right: &str) -> serde_yml::Value {
+        let left: serde_yml::Value = serde_yml::from_str(left).unwrap();
+        let right: serde_yml::Value = serde_yml::from_str(right).unwrap();
+        let patch = merge_patch(&left, &right, &DiffOptions::default()).unwrap();
+        let mut patched = left.clone();
+        apply_merge(&mut patched, &patch);
+        assert_eq!(patched, right, "patch: {patch:?}");
+        patch
+    }
+
+    #[test]
+    fn merge_patch_round_trip() {
+        let patch = merge_round_trip(
+            "a: 1\nb: {c: 2, d: 3}\nlist: [1, 2, 3]\nsame: x\n",
+            "a: 1\nb: {c: 5, e: {f: 1}}\nlist: [1, 3]\nsame: x\nnew: [null]\n",
+        );
+        let expected: serde_yml::Value =
+            serde_yml::from_str("b: {c: 5, d: null, e: {f: 1}}\nlist: [1, 3]\nnew: [null]\n")
+                .unwrap();
+        assert_eq!(patch, expected);
+        merge_round_trip("a: [1]", "a: {b: 2}");
+        merge_round_trip("a: {b: 2}", "- 1\n- 2\n");
+    }
+
+    #[test]
+    fn merge_patch_keeps_tags() {
+        let patch = merge_round_trip("a: !Ref X\n", "a: !Sub X\nb: !Ref Y\n");
+        let expected: serde_yml::Value = serde_yml::from_str("a: !Sub X\nb: !Ref Y\n").unwrap();
+        assert_eq!(patch, expected);
+        merge_round_trip("a: !If [c, x]\n", "a: !Or [c, x]\n");
+    }
+
+    #[test]
+    fn merge_patch_of_unchanged_is_empty() {
+        let patch = merge_round_trip("a: {b: 1}", "a: {b: 1}");
+        assert_eq!(patch, serde_yml::Value::Mapping(serde_yml::Mapping::new()));
+    }
+
+    #[test]
+    fn merge_patch_rejects_explicit_nulls() {
+        let left: serde_yml::Value = serde_yml::from_str("a: 1\nb: {c: 1}\n").unwrap();
+        let right: serde_yml::Value =
+            serde_yml::from_str("a: null\nb: {c: 1, d: {e: ~}}\n").unwrap();
+        let diffs = yaml_diff(&left, &right, &DiffContext::new(&DiffOptions::default())).unwrap();
+        assert_eq!(
+            diff_to_merge_patch(&left, &right, &diffs),
+            Err(vec!["/a".to_string(), "/b/d/e".to_string()])
+        );
+    }
+}
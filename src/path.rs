@@ -0,0 +1,320 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// One step from a parent node to a child: a mapping key or a sequence index.
+/// Serialized as `{key: name}` or `{index: 0}`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(into = "SegmentRepr", try_from = "SegmentRepr")]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// The serialized form of a `PathSegment`, spelled out as a one-entry
+/// mapping: serde_yml would otherwise write the enum as a YAML tag.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SegmentRepr {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    index: Option<usize>,
+}
+
+impl From<PathSegment> for SegmentRepr {
+    fn from(segment: PathSegment) -> Self {
+        match segment {
+            PathSegment::Key(key) => Self {
+                key: Some(key),
+                index: None,
+            },
+            PathSegment::Index(index) => Self {
+                key: None,
+                index: Some(index),
+            },
+        }
+    }
+}
+
+impl TryFrom<SegmentRepr> for PathSegment {
+    type Error = &'static str;
+
+    fn try_from(repr: SegmentRepr) -> Result<Self, Self::Error> {
+        match repr {
+            SegmentRepr {
+                key: Some(key),
+                index: None,
+            } => Ok(PathSegment::Key(key)),
+            SegmentRepr {
+                key: None,
+                index: Some(index),
+            } => Ok(PathSegment::Index(index)),
+            _ => Err("a path segment needs exactly one of `key` or `index`"),
+        }
+    }
+}
+
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSegment::Key(key) => f.write_str(key),
+            PathSegment::Index(index) => write!(f, "{index}"),
+        }
+    }
+}
+
+/// Escape one reference token of a JSON Pointer: `~` becomes `~0` and `/`
+/// becomes `~1`, in that order.
+pub(crate) fn escape_pointer_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+/// Render a path as an RFC 6901 JSON Pointer, `""` for the root.
+pub fn json_pointer(path: &[PathSegment]) -> String {
+    path.iter()
+        .map(|segment| format!("/{}", escape_pointer_token(&segment.to_string())))
+        .collect()
+}
+
+/// Render a path as a yq expression: `.spec.containers[0].name`, `.` for
+/// the root. Keys that are not plain identifiers are quoted, as in
+/// `.metadata.labels["app.kubernetes.io/name"]`.
+pub fn dotted_path(path: &[PathSegment]) -> String {
+    if path.is_empty() {
+        return ".".to_string();
+    }
+    let mut out = String::new();
+    for segment in path {
+        match segment {
+            PathSegment::Index(index) => out.push_str(&format!("[{index}]")),
+            PathSegment::Key(key) if is_identifier(key) => {
+                out.push('.');
+                out.push_str(key);
+            }
+            PathSegment::Key(key) => {
+                let escaped = key.replace('\\', "\\\\").replace('"', "\\\"");
+                out.push_str(&format!("[\"{escaped}\"]"));
+            }
+        }
+    }
+    if out.starts_with('[') {
+        out.insert(0, '.');
+    }
+    out
+}
+
+/// Whether `key` can follow a `.` in a yq path without quoting.
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum PatternSegment {
+    Literal(String),
+    /// `*` — exactly one segment, key or index.
+    Any,
+    /// `**` — any number of segments, including none.
+    AnyDepth,
+    /// A segment with embedded wildcards such as `kubectl*`.
+    Glob(String),
+}
+
+impl PatternSegment {
+    fn matches(&self, segment: &PathSegment) -> bool {
+        match (self, segment) {
+            (PatternSegment::Any | PatternSegment::AnyDepth, _) => true,
+            (PatternSegment::Glob(glob), segment) => glob_matches(glob, &segment.to_string()),
+            (PatternSegment::Literal(lit), PathSegment::Key(key)) => lit == key,
+            (PatternSegment::Literal(lit), PathSegment::Index(index)) => {
+                lit.parse::<usize>().is_ok_and(|i| i == *index)
+            }
+        }
+    }
+}
+
+/// Match `text` against `glob`, where `*` stands for any run of characters.
+fn glob_matches(glob: &str, text: &str) -> bool {
+    let (glob, text): (Vec<char>, Vec<char>) = (glob.chars().collect(), text.chars().collect());
+    let (mut g, mut t) = (0, 0);
+    // Position of the last `*` seen and the text position it was tried at.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if g < glob.len() && glob[g] == '*' {
+            backtrack = Some((g, t));
+            g += 1;
+        } else if g < glob.len() && glob[g] == text[t] {
+            g += 1;
+            t += 1;
+        } else if let Some((star, start)) = backtrack {
+            g = star + 1;
+            t = start + 1;
+            backtrack = Some((star, start + 1));
+        } else {
+            return false;
+        }
+    }
+
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+fn matches_from(patterns: &[PatternSegment], path: &[PathSegment]) -> bool {
+    match patterns.split_first() {
+        None => path.is_empty(),
+        Some((PatternSegment::AnyDepth, rest)) => {
+            (0..=path.len()).any(|skip| matches_from(rest, &path[skip..]))
+        }
+        Some((pattern, rest)) => path
+            .split_first()
+            .is_some_and(|(head, tail)| pattern.matches(head) && matches_from(rest, tail)),
+    }
+}
+
+/// A dotted path pattern such as `spec.template.spec.containers` or
+/// `jobs.*.steps`, matched against the path of a node from the document
+/// root. `*` stands for any single key or index, `**` for any number of
+/// them, and `*` inside a segment (`kubectl*`) for any run of characters.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(from = "String")]
+pub struct PathPattern {
+    segments: Vec<PatternSegment>,
+}
+
+impl PathPattern {
+    pub fn parse(pattern: &str) -> Self {
+        let segments = pattern
+            .split('.')
+            .filter(|s| !s.is_empty())
+            .map(|s| match s {
+                "*" => PatternSegment::Any,
+                "**" => PatternSegment::AnyDepth,
+                glob if glob.contains('*') => PatternSegment::Glob(glob.to_string()),
+                lit => PatternSegment::Literal(lit.to_string()),
+            })
+            .collect();
+        Self { segments }
+    }
+
+    pub fn matches(&self, path: &[PathSegment]) -> bool {
+        matches_from(&self.segments, path)
+    }
+}
+
+impl From<String> for PathPattern {
+    fn from(pattern: String) -> Self {
+        Self::parse(&pattern)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(segments: &[&str]) -> Vec<PathSegment> {
+        segments
+            .iter()
+            .map(|s| match s.parse::<usize>() {
+                Ok(i) => PathSegment::Index(i),
+                Err(_) => PathSegment::Key(s.to_string()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pointer_tokens_are_escaped() {
+        assert_eq!(escape_pointer_token("a/b~c"), "a~1b~0c");
+        assert_eq!(escape_pointer_token("~1"), "~01");
+        assert_eq!(json_pointer(&path(&["a/b", "0", "~k"])), "/a~1b/0/~0k");
+        assert_eq!(json_pointer(&[]), "");
+    }
+
+    #[test]
+    fn dotted_paths_quote_unusual_keys() {
+        assert_eq!(dotted_path(&[]), ".");
+        assert_eq!(
+            dotted_path(&path(&["spec", "containers", "0", "name"])),
+            ".spec.containers[0].name"
+        );
+        assert_eq!(dotted_path(&path(&["0", "a"])), ".[0].a");
+        assert_eq!(
+            dotted_path(&[
+                PathSegment::Key("labels".to_string()),
+                PathSegment::Key("app.kubernetes.io/name".to_string()),
+                PathSegment::Key("say \"hi\"".to_string()),
+            ]),
+            r#".labels["app.kubernetes.io/name"]["say \"hi\""]"#
+        );
+        assert_eq!(
+            dotted_path(&[PathSegment::Key("a b".to_string())]),
+            r#".["a b"]"#
+        );
+    }
+
+    #[test]
+    fn segments_serialize_as_single_entry_mappings() {
+        let segments = path(&["items", "0"]);
+        let text = serde_yml::to_string(&segments).unwrap();
+        assert_eq!(text, "- key: items\n- index: 0\n");
+        assert_eq!(
+            serde_yml::from_str::<Vec<PathSegment>>(&text).unwrap(),
+            segments
+        );
+        assert!(serde_yml::from_str::<PathSegment>("{key: a, index: 0}").is_err());
+    }
+
+    #[test]
+    fn literal_pattern() {
+        let pattern = PathPattern::parse("spec.containers");
+        assert!(pattern.matches(&path(&["spec", "containers"])));
+        assert!(!pattern.matches(&path(&["spec"])));
+        assert!(!pattern.matches(&path(&["spec", "containers", "0"])));
+    }
+
+    #[test]
+    fn wildcard_matches_keys_and_indices() {
+        let pattern = PathPattern::parse("jobs.*.steps");
+        assert!(pattern.matches(&path(&["jobs", "build", "steps"])));
+        assert!(pattern.matches(&path(&["jobs", "3", "steps"])));
+        assert!(!pattern.matches(&path(&["jobs", "build", "env"])));
+    }
+
+    #[test]
+    fn index_literal() {
+        let pattern = PathPattern::parse("items.1.env");
+        assert!(pattern.matches(&path(&["items", "1", "env"])));
+        assert!(!pattern.matches(&path(&["items", "2", "env"])));
+    }
+
+    #[test]
+    fn glob_within_segment() {
+        let pattern = PathPattern::parse("metadata.annotations.kubectl*");
+        assert!(pattern.matches(&[
+            PathSegment::Key("metadata".to_string()),
+            PathSegment::Key("annotations".to_string()),
+            PathSegment::Key("kubectl.kubernetes.io/last-applied-configuration".to_string()),
+        ]));
+        assert!(!pattern.matches(&path(&["metadata", "annotations", "team"])));
+        assert!(PathPattern::parse("a*c*e").matches(&path(&["abcdce"])));
+        assert!(!PathPattern::parse("a*c*e").matches(&path(&["abcdcf"])));
+    }
+
+    #[test]
+    fn double_wildcard_spans_any_depth() {
+        let pattern = PathPattern::parse("**.generation");
+        assert!(pattern.matches(&path(&["generation"])));
+        assert!(pattern.matches(&path(&["metadata", "generation"])));
+        assert!(pattern.matches(&path(&["items", "0", "metadata", "generation"])));
+        assert!(!pattern.matches(&path(&["metadata", "name"])));
+        assert!(PathPattern::parse("spec.**").matches(&path(&["spec"])));
+    }
+
+    #[test]
+    fn empty_pattern_matches_root() {
+        assert!(PathPattern::parse("").matches(&[]));
+    }
+}
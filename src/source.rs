@@ -0,0 +1,374 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_yml::libyml::parser::{Event, Parser, Scalar, ScalarStyle};
+
+use crate::diff::{yaml_key_to_string, YamlDiff};
+use crate::options::DiffOptions;
+use crate::path::PathSegment;
+
+/// A place in YAML source text. Lines and columns start at 1, as in parse
+/// errors, and columns count characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Where a value sits in its source text, from its first character up to,
+/// not including, the position after its last.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    span: Span,
+    sequence: bool,
+}
+
+/// The span of every value in a YAML stream, by document and path.
+/// `serde_yml::Value` drops source marks, so this reads the text again
+/// through the parser's event stream. Mapping keys are rendered the way
+/// the diff renders them, and a value reached through an alias spans the
+/// alias.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    documents: Vec<HashMap<Vec<PathSegment>, Entry>>,
+}
+
+/// A collection whose contents are still being read.
+struct Frame {
+    /// `None` inside a complex mapping key, whose contents have no path.
+    path: Option<Vec<PathSegment>>,
+    start: usize,
+    end: usize,
+    kind: FrameKind,
+}
+
+enum FrameKind {
+    Sequence {
+        next: usize,
+    },
+    /// `key` holds the last key read while its value is pending, `None`
+    /// when the next node is a key.
+    Mapping {
+        key: Option<Option<String>>,
+    },
+}
+
+impl SourceMap {
+    /// Read the spans of `text`. Text that fails to parse keeps the spans
+    /// of what came before the error.
+    pub fn new(text: &str) -> Self {
+        let lines = LineIndex::new(text);
+        let mut documents = Vec::new();
+        let mut stack: Vec<Frame> = Vec::new();
+        let mut parser = Parser::new(Cow::Borrowed(text.as_bytes()));
+
+        while let Ok((event, mark)) = parser.parse_next_event() {
+            let start = mark.index() as usize;
+            let (path, end) = match event {
+                Event::StreamEnd => break,
+                Event::StreamStart | Event::DocumentEnd => continue,
+                Event::DocumentStart => {
+                    documents.push(HashMap::new());
+                    stack.clear();
+                    continue;
+                }
+                Event::SequenceStart(_) | Event::MappingStart(_) => {
+                    let path = next_path(&mut stack, None);
+                    let kind = match event {
+                        Event::SequenceStart(_) => FrameKind::Sequence { next: 0 },
+                        _ => FrameKind::Mapping { key: None },
+                    };
+                    stack.push(Frame {
+                        path,
+                        start,
+                        end: start,
+                        kind,
+                    });
+                    continue;
+                }
+                Event::SequenceEnd | Event::MappingEnd => {
+                    let Some(frame) = stack.pop() else { continue };
+                    // Flow collections end at their closing bracket, block
+                    // ones with their last entry.
+                    let end = match text.as_bytes().get(start) {
+                        Some(b']' | b'}') => start + 1,
+                        _ => frame.end,
+                    };
+                    let sequence = matches!(frame.kind, FrameKind::Sequence { .. });
+                    if let (Some(path), Some(document)) = (frame.path, documents.last_mut()) {
+                        let span = lines.span(frame.start, end);
+                        document.insert(path, Entry { span, sequence });
+                    }
+                    (None, end)
+                }
+                Event::Scalar(scalar) => {
+                    let end = start + scalar.repr.map_or(scalar.value.len(), <[u8]>::len);
+                    (next_path(&mut stack, Some(&scalar)), end)
+                }
+                Event::Alias(_) => {
+                    let name = text[start + 1..]
+                        .find(|c: char| c.is_whitespace() || ",[]{}".contains(c))
+                        .unwrap_or(text.len() - start - 1);
+                    (next_path(&mut stack, None), start + 1 + name)
+                }
+            };
+            if let (Some(path), Some(document)) = (path, documents.last_mut()) {
+                let span = lines.span(start, end);
+                document.insert(
+                    path,
+                    Entry {
+                        span,
+                        sequence: false,
+                    },
+                );
+            }
+            if let Some(parent) = stack.last_mut() {
+                parent.end = parent.end.max(end);
+            }
+        }
+
+        Self { documents }
+    }
+
+    fn entry(&self, document: usize, path: &[PathSegment]) -> Option<&Entry> {
+        self.documents.get(document)?.get(path)
+    }
+}
+
+/// The path of the node that starts now, advancing the collection it is
+/// in. `None` for mapping keys and anything inside them. A scalar key is
+/// remembered for the value that follows.
+fn next_path(stack: &mut [Frame], scalar: Option<&Scalar<'_>>) -> Option<Vec<PathSegment>> {
+    let Some(frame) = stack.last_mut() else {
+        return Some(Vec::new());
+    };
+    let segment = match &mut frame.kind {
+        FrameKind::Sequence { next } => {
+            *next += 1;
+            PathSegment::Index(*next - 1)
+        }
+        FrameKind::Mapping { key } => match key.take() {
+            Some(Some(key)) => PathSegment::Key(key),
+            Some(None) => return None,
+            None => {
+                *key = Some(scalar.map(key_string));
+                return None;
+            }
+        },
+    };
+    let mut path = frame.path.clone()?;
+    path.push(segment);
+    Some(path)
+}
+
+/// A scalar key as the diff names it: plain scalars are resolved first,
+/// so `~` and `0x10` come out as they would from a parsed `Value`.
+fn key_string(scalar: &Scalar<'_>) -> String {
+    let text = String::from_utf8_lossy(&scalar.value);
+    if scalar.style == ScalarStyle::Plain {
+        if let Ok(value) = serde_yml::from_str::<serde_yml::Value>(&text) {
+            return yaml_key_to_string(&value);
+        }
+    }
+    text.into_owned()
+}
+
+/// Byte offsets of line starts, to turn offsets into positions.
+struct LineIndex<'a> {
+    text: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { text, starts }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.starts[line];
+        let column = self
+            .text
+            .get(start..offset)
+            .map_or(offset - start, |s| s.chars().count());
+        Position {
+            line: line + 1,
+            column: column + 1,
+        }
+    }
+
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span {
+            start: self.position(start),
+            end: self.position(end),
+        }
+    }
+}
+
+/// A node's place on one side: its document and path there.
+#[derive(Clone)]
+struct Cursor<'a> {
+    source: &'a SourceMap,
+    document: usize,
+    path: Vec<PathSegment>,
+}
+
+impl<'a> Cursor<'a> {
+    fn root(source: &'a SourceMap, document: usize) -> Option<Self> {
+        let cursor = Self {
+            source,
+            document,
+            path: Vec::new(),
+        };
+        cursor.entry().is_some().then_some(cursor)
+    }
+
+    fn entry(&self) -> Option<&'a Entry> {
+        self.source.entry(self.document, &self.path)
+    }
+
+    /// Where `node`, a child of this node, is on this side. Elements are
+    /// found by their index on this side, which the diff records on them.
+    /// Elements under a whole addition or deletion record neither index and
+    /// are keyed by their position instead.
+    fn child(&self, node: &YamlDiff, index: Option<usize>) -> Option<Self> {
+        let Some(key) = &node.key else {
+            return Some(self.clone());
+        };
+        let segment = if self.entry()?.sequence {
+            let unindexed = node.left_index.is_none() && node.right_index.is_none();
+            match index {
+                Some(index) => PathSegment::Index(index),
+                None if unindexed => PathSegment::Index(key.parse().ok()?),
+                None => return None,
+            }
+        } else {
+            PathSegment::Key(key.clone())
+        };
+        let mut path = self.path.clone();
+        path.push(segment);
+        let child = Self {
+            path,
+            ..self.clone()
+        };
+        child.entry().is_some().then_some(child)
+    }
+}
+
+/// Fill in `left_span` and `right_span` across a diff of the streams in
+/// `left` and `right`, as returned by `diff_documents` with the same
+/// `options` for their parsed documents.
+pub fn attach_spans(diffs: &mut [YamlDiff], left: &str, right: &str, options: &DiffOptions) {
+    let (left, right) = (SourceMap::new(left), SourceMap::new(right));
+    let per_document = options.kubernetes || left.documents.len() > 1 || right.documents.len() > 1;
+    if !per_document {
+        attach_children(diffs, Cursor::root(&left, 0), Cursor::root(&right, 0));
+        return;
+    }
+    // One node per document, which records its position in each stream.
+    for node in diffs {
+        let left = node.left_index.and_then(|i| Cursor::root(&left, i));
+        let right = node.right_index.and_then(|i| Cursor::root(&right, i));
+        attach(node, left, right);
+    }
+}
+
+fn attach_children(nodes: &mut [YamlDiff], left: Option<Cursor>, right: Option<Cursor>) {
+    for node in nodes {
+        let node_left = left.as_ref().and_then(|c| c.child(node, node.left_index));
+        let node_right = right.as_ref().and_then(|c| c.child(node, node.right_index));
+        attach(node, node_left, node_right);
+    }
+}
+
+fn attach(node: &mut YamlDiff, left: Option<Cursor>, right: Option<Cursor>) {
+    node.left_span = left.as_ref().and_then(Cursor::entry).map(|e| e.span);
+    node.right_span = right.as_ref().and_then(Cursor::entry).map(|e| e.span);
+    attach_children(&mut node.children, left, right);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(
+        source: &SourceMap,
+        document: usize,
+        path: &[PathSegment],
+    ) -> (usize, usize, usize, usize) {
+        let Span { start, end } = source.entry(document, path).unwrap().span;
+        (start.line, start.column, end.line, end.column)
+    }
+
+    fn key(k: &str) -> PathSegment {
+        PathSegment::Key(k.to_string())
+    }
+
+    #[test]
+    fn spans_of_block_and_flow_values() {
+        let source =
+            SourceMap::new("name: web\nports:\n  - 80\n  - [1, 2]\nlabels: {app: \"é\"}\n");
+        assert_eq!(span(&source, 0, &[]), (1, 1, 5, 19));
+        assert_eq!(span(&source, 0, &[key("name")]), (1, 7, 1, 10));
+        assert_eq!(span(&source, 0, &[key("ports")]), (3, 3, 4, 11));
+        assert_eq!(
+            span(&source, 0, &[key("ports"), PathSegment::Index(1)]),
+            (4, 5, 4, 11)
+        );
+        assert_eq!(span(&source, 0, &[key("labels")]), (5, 9, 5, 19));
+        assert_eq!(
+            span(&source, 0, &[key("labels"), key("app")]),
+            (5, 15, 5, 18)
+        );
+    }
+
+    #[test]
+    fn documents_aliases_and_resolved_keys() {
+        let source = SourceMap::new("a: &x 1\nb: *x\n---\n1.0: |\n  text\n~: null\n0x10: a\n");
+        assert_eq!(span(&source, 0, &[key("b")]), (2, 4, 2, 6));
+        assert_eq!(span(&source, 1, &[key("1.0")]), (4, 6, 6, 1));
+        assert_eq!(span(&source, 1, &[key("null")]), (6, 4, 6, 8));
+        assert_eq!(span(&source, 1, &[key("16")]), (7, 7, 7, 8));
+    }
+
+    #[test]
+    fn spans_attached_to_both_sides() {
+        let (left, right) = ("a: 1\nlist:\n  - x\n", "list:\n  - y\n  - x\na: 2\n");
+        let options = DiffOptions::default();
+        let mut diffs = crate::diff_documents(
+            &crate::read_yaml(left).unwrap(),
+            &crate::read_yaml(right).unwrap(),
+            &options,
+        )
+        .unwrap();
+        attach_spans(&mut diffs, left, right, &options);
+
+        let at = |span: Option<Span>| span.map(|s| (s.start.line, s.start.column));
+        let a = &diffs[0];
+        assert_eq!(
+            (at(a.left_span), at(a.right_span)),
+            (Some((1, 4)), Some((4, 4)))
+        );
+        let list = &diffs[1].children;
+        let added = list.iter().find(|n| n.right_index == Some(0)).unwrap();
+        assert_eq!(
+            (at(added.left_span), at(added.right_span)),
+            (None, Some((2, 5)))
+        );
+        let kept = list.iter().find(|n| n.left_index == Some(0)).unwrap();
+        assert_eq!(
+            (at(kept.left_span), at(kept.right_span)),
+            (Some((3, 5)), Some((3, 5)))
+        );
+    }
+}
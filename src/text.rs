@@ -0,0 +1,271 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::options::InlineGranularity;
+
+/// What a `LineHunk` does to its lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineChange {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// A run of lines in the line-level diff of a changed multi-line string,
+/// as 0-based, end-exclusive line ranges of the left and right strings.
+/// The range on the side a hunk doesn't touch is empty and marks where it
+/// applies. Hunks are in order and together cover both strings.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LineHunk {
+    pub change: LineChange,
+    pub left_lines: Range<usize>,
+    pub right_lines: Range<usize>,
+}
+
+/// Where two single-line strings differ, as 0-based, end-exclusive char
+/// offsets: the parts of the left string that were removed and of the
+/// right string that were inserted.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InlineChanges {
+    pub left: Vec<Range<usize>>,
+    pub right: Vec<Range<usize>>,
+}
+
+/// Whether either string spans several lines.
+pub(crate) fn is_multi_line(left: &str, right: &str) -> bool {
+    left.contains('\n') || right.contains('\n')
+}
+
+/// The line-level diff of two strings when either spans several lines,
+/// `None` otherwise, or when the product of their line counts exceeds
+/// `limit`. A replaced run of lines becomes a deletion followed by an
+/// insertion, as in a unified diff.
+pub(crate) fn line_hunks(left: &str, right: &str, limit: usize) -> Option<Vec<LineHunk>> {
+    if !is_multi_line(left, right) {
+        return None;
+    }
+    let lines = |text: &str| text.split_inclusive('\n').count();
+    if lines(left).saturating_mul(lines(right)) > limit {
+        return None;
+    }
+    let diff = similar::TextDiff::from_lines(left, right);
+    let mut hunks = Vec::new();
+    for op in diff.ops() {
+        let (left_lines, right_lines) = (op.old_range(), op.new_range());
+        let mut push = |change, left_lines: Range<usize>, right_lines: Range<usize>| {
+            hunks.push(LineHunk {
+                change,
+                left_lines,
+                right_lines,
+            })
+        };
+        match op.tag() {
+            similar::DiffTag::Equal => push(LineChange::Equal, left_lines, right_lines),
+            similar::DiffTag::Delete => push(LineChange::Delete, left_lines, right_lines),
+            similar::DiffTag::Insert => push(LineChange::Insert, left_lines, right_lines),
+            similar::DiffTag::Replace => {
+                let (left_end, right_start) = (left_lines.end, right_lines.start);
+                push(LineChange::Delete, left_lines, right_start..right_start);
+                push(LineChange::Insert, left_end..left_end, right_lines);
+            }
+        }
+    }
+    Some(hunks)
+}
+
+/// Characters that end a word, so that `nginx:1.25` to `nginx:1.26` or a
+/// changed URL segment marks only the part that changed.
+const WORD_SEPARATORS: [char; 7] = ['/', ':', '.', '-', '_', '@', '='];
+
+/// The changed parts of two single-line strings at `granularity`, `None`
+/// when it is `none` or when the product of their token counts exceeds
+/// `limit`.
+pub(crate) fn inline_changes(
+    left: &str,
+    right: &str,
+    granularity: InlineGranularity,
+    limit: usize,
+) -> Option<InlineChanges> {
+    let tokenize = match granularity {
+        InlineGranularity::None => return None,
+        InlineGranularity::Word => words,
+        InlineGranularity::Char => chars,
+    };
+    let (left_tokens, right_tokens) = (tokenize(left), tokenize(right));
+    if left_tokens.len().saturating_mul(right_tokens.len()) > limit {
+        return None;
+    }
+    let left_offsets = char_offsets(&left_tokens);
+    let right_offsets = char_offsets(&right_tokens);
+    let mut changes = InlineChanges::default();
+    for op in similar::capture_diff_slices(similar::Algorithm::Myers, &left_tokens, &right_tokens) {
+        let (left_tokens, right_tokens) = (op.old_range(), op.new_range());
+        if op.tag() == similar::DiffTag::Equal {
+            continue;
+        }
+        extend(
+            &mut changes.left,
+            left_offsets[left_tokens.start]..left_offsets[left_tokens.end],
+        );
+        extend(
+            &mut changes.right,
+            right_offsets[right_tokens.start]..right_offsets[right_tokens.end],
+        );
+    }
+    Some(changes)
+}
+
+/// Split a string into runs of whitespace, single `WORD_SEPARATORS` and
+/// the words between them.
+fn words(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        let next = chars.peek().copied();
+        let ends_token = match next {
+            None => true,
+            Some((_, next)) => {
+                WORD_SEPARATORS.contains(&c)
+                    || WORD_SEPARATORS.contains(&next)
+                    || c.is_whitespace() != next.is_whitespace()
+            }
+        };
+        if ends_token {
+            let end = next.map_or(text.len(), |(i, _)| i);
+            tokens.push(&text[start..end]);
+            start = end;
+        }
+    }
+    tokens
+}
+
+/// Split a string into its characters.
+fn chars(text: &str) -> Vec<&str> {
+    text.char_indices()
+        .map(|(i, c)| &text[i..i + c.len_utf8()])
+        .collect()
+}
+
+/// Char offset at which each token starts, plus the total length.
+fn char_offsets(tokens: &[&str]) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(tokens.len() + 1);
+    let mut offset = 0;
+    offsets.push(offset);
+    for token in tokens {
+        offset += token.chars().count();
+        offsets.push(offset);
+    }
+    offsets
+}
+
+/// Add a range, merging it into the last one when they touch.
+fn extend(ranges: &mut Vec<Range<usize>>, range: Range<usize>) {
+    if range.is_empty() {
+        return;
+    }
+    match ranges.last_mut() {
+        Some(last) if last.end == range.start => last.end = range.end,
+        _ => ranges.push(range),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hunk(change: LineChange, left_lines: Range<usize>, right_lines: Range<usize>) -> LineHunk {
+        LineHunk {
+            change,
+            left_lines,
+            right_lines,
+        }
+    }
+
+    #[test]
+    fn changed_line_is_a_deletion_and_an_insertion() {
+        assert_eq!(
+            line_hunks(
+                "set -e\necho a\nexit 0\n",
+                "set -e\necho b\nexit 0\nrm x\n",
+                usize::MAX
+            )
+            .unwrap(),
+            vec![
+                hunk(LineChange::Equal, 0..1, 0..1),
+                hunk(LineChange::Delete, 1..2, 1..1),
+                hunk(LineChange::Insert, 2..2, 1..2),
+                hunk(LineChange::Equal, 2..3, 2..3),
+                hunk(LineChange::Insert, 3..3, 3..4),
+            ]
+        );
+    }
+
+    #[test]
+    fn single_line_strings_have_no_hunks() {
+        assert_eq!(line_hunks("nginx:1.25", "nginx:1.26", usize::MAX), None);
+    }
+
+    #[test]
+    fn long_strings_have_no_hunks() {
+        let (left, right) = ("a\nb\nc\n", "a\nx\nc\n");
+        assert!(line_hunks(left, right, 9).is_some());
+        assert_eq!(line_hunks(left, right, 8), None);
+    }
+
+    fn pairs(ranges: &[Range<usize>]) -> Vec<(usize, usize)> {
+        ranges
+            .iter()
+            .map(|range| (range.start, range.end))
+            .collect()
+    }
+
+    #[test]
+    fn inline_changes_by_granularity() {
+        let changes = |granularity| {
+            inline_changes(
+                "registry/nginx:1.25 é",
+                "registry/nginx:1.26 é",
+                granularity,
+                usize::MAX,
+            )
+        };
+        let by_char = changes(InlineGranularity::Char).unwrap();
+        assert_eq!(pairs(&by_char.left), [(18, 19)]);
+        assert_eq!(pairs(&by_char.right), [(18, 19)]);
+        let by_word = changes(InlineGranularity::Word).unwrap();
+        assert_eq!(pairs(&by_word.left), [(17, 19)]);
+        assert_eq!(pairs(&by_word.right), [(17, 19)]);
+        assert_eq!(changes(InlineGranularity::None), None);
+
+        let appended =
+            inline_changes("run a b", "run a b c", InlineGranularity::Word, usize::MAX).unwrap();
+        assert!(appended.left.is_empty());
+        assert_eq!(pairs(&appended.right), [(7, 9)]);
+    }
+
+    #[test]
+    fn long_strings_have_no_inline_changes() {
+        let (left, right) = ("nginx:1.25", "nginx:1.26");
+        assert!(inline_changes(left, right, InlineGranularity::Word, 25).is_some());
+        assert_eq!(
+            inline_changes(left, right, InlineGranularity::Word, 24),
+            None
+        );
+        assert_eq!(
+            inline_changes(left, right, InlineGranularity::Char, 99),
+            None
+        );
+    }
+
+    #[test]
+    fn words_break_at_separators() {
+        assert_eq!(
+            words("https://ex.com/a_b  x=1"),
+            ["https", ":", "/", "/", "ex", ".", "com", "/", "a", "_", "b", "  ", "x", "=", "1"]
+        );
+        assert_eq!(words(""), Vec::<&str>::new());
+    }
+}
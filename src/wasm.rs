@@ -0,0 +1,237 @@
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::diff::{documents_diff, DiffReport, YamlDiff};
+use crate::options::DiffOptions;
+use crate::patch::{json_patch, merge_patch};
+use crate::source::attach_spans;
+use crate::{apply, diff_documents, merge, path, read_yaml};
+
+/// Hand a diff tree to JS as a `DiffReport`: plain objects, `diff_type`
+/// as a string and mappings as objects.
+fn report_to_js(diffs: Vec<YamlDiff>) -> Result<JsValue, JsError> {
+    DiffReport::new(diffs)
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|e| JsError::new(&format!("Serialization error: {e}")))
+}
+
+fn format_parse_error(label: &str, e: &serde_yml::Error) -> String {
+    match e.location() {
+        Some(loc) => format!("[{label}] Error: {e} at line: {}", loc.line()),
+        None => format!("[{label}] Error: {e}"),
+    }
+}
+
+fn validate_and_parse(
+    yone: &str,
+    ytwo: &str,
+) -> Result<(Vec<serde_yml::Value>, Vec<serde_yml::Value>), JsError> {
+    let yone_trimmed = yone.trim();
+    let ytwo_trimmed = ytwo.trim();
+
+    if yone_trimmed.is_empty() && ytwo_trimmed.is_empty() {
+        return Err(JsError::new(
+            "[YAML ONE] Error: empty input\n[YAML TWO] Error: empty input",
+        ));
+    }
+    if yone_trimmed.is_empty() {
+        return Err(JsError::new("[YAML ONE] Error: empty input"));
+    }
+    if ytwo_trimmed.is_empty() {
+        return Err(JsError::new("[YAML TWO] Error: empty input"));
+    }
+
+    let parsed_one = read_yaml(yone);
+    let parsed_two = read_yaml(ytwo);
+
+    match (parsed_one, parsed_two) {
+        (Ok(one), Ok(two)) => Ok((one, two)),
+        (Err(e1), Err(e2)) => {
+            let msg = format!(
+                "{}\n{}",
+                format_parse_error("YAML ONE", &e1),
+                format_parse_error("YAML TWO", &e2)
+            );
+            Err(JsError::new(&msg))
+        }
+        (Err(e), Ok(_)) => Err(JsError::new(&format_parse_error("YAML ONE", &e))),
+        (Ok(_), Err(e)) => Err(JsError::new(&format_parse_error("YAML TWO", &e))),
+    }
+}
+
+/// Compute a complete diff of two YAML strings in one call.
+/// Handles all top-level types: mappings, sequences, scalars, and mixed.
+/// Multi-document streams produce one top-level node per document index.
+/// Nodes carry the `left_span` and `right_span` of their values.
+#[wasm_bindgen]
+pub fn compute_diff(yone: &str, ytwo: &str) -> Result<JsValue, JsError> {
+    let (one, two) = validate_and_parse(yone, ytwo)?;
+    let options = DiffOptions::default();
+    let mut diffs = documents_diff(&one, &two, &options)
+        .map_err(|e| JsError::new(&format!("Diff error: {e}")))?;
+    attach_spans(&mut diffs, yone, ytwo, &options);
+    report_to_js(diffs)
+}
+
+/// Deserialize the options object passed from JS. `undefined` and `null`
+/// mean all defaults.
+fn parse_options(options: JsValue) -> Result<DiffOptions, JsError> {
+    if options.is_undefined() || options.is_null() {
+        return Ok(DiffOptions::default());
+    }
+    let options: DiffOptions = serde_wasm_bindgen::from_value(options)
+        .map_err(|e| JsError::new(&format!("Invalid options: {e}")))?;
+    options
+        .validate()
+        .map_err(|e| JsError::new(&e.to_string()))?;
+    Ok(options)
+}
+
+/// Like `compute_diff`, configured by an options object whose fields
+/// mirror `DiffOptions`, e.g.
+/// `{ ignore_paths: ["status"], sequence_keys: [{ path: "spec.containers", fields: ["name"] }] }`.
+#[wasm_bindgen]
+pub fn compute_diff_with_options(
+    yone: &str,
+    ytwo: &str,
+    options: JsValue,
+) -> Result<JsValue, JsError> {
+    let options = parse_options(options)?;
+    let (one, two) = validate_and_parse(yone, ytwo)?;
+    let mut diffs = diff_documents(&one, &two, &options)
+        .map_err(|e| JsError::new(&format!("Diff error: {e}")))?;
+    attach_spans(&mut diffs, yone, ytwo, &options);
+    report_to_js(diffs)
+}
+
+/// Patches describe one document; reject multi-document streams.
+fn single_documents<'a>(
+    one: &'a [serde_yml::Value],
+    two: &'a [serde_yml::Value],
+    format: &str,
+) -> Result<(&'a serde_yml::Value, &'a serde_yml::Value), JsError> {
+    match (one, two) {
+        ([one], [two]) => Ok((one, two)),
+        _ => Err(JsError::new(&format!(
+            "{format} needs exactly one document on each side"
+        ))),
+    }
+}
+
+/// RFC 6902 JSON Patch turning the first YAML document into the second,
+/// as an array of `{ op, path, from?, value? }` objects. Takes the same
+/// options as `compute_diff_with_options`. Both inputs must hold exactly
+/// one document.
+#[wasm_bindgen]
+pub fn compute_json_patch(yone: &str, ytwo: &str, options: JsValue) -> Result<JsValue, JsError> {
+    let options = parse_options(options)?;
+    let (one, two) = validate_and_parse(yone, ytwo)?;
+    let (one, two) = single_documents(&one, &two, "JSON Patch")?;
+    let ops =
+        json_patch(one, two, &options).map_err(|e| JsError::new(&format!("Diff error: {e}")))?;
+    ops.serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|e| JsError::new(&format!("Serialization error: {e}")))
+}
+
+/// RFC 7396 JSON Merge Patch turning the first YAML document into the
+/// second. Fails when the second document sets a mapping member to null,
+/// which a merge patch cannot express.
+#[wasm_bindgen]
+pub fn compute_merge_patch(yone: &str, ytwo: &str, options: JsValue) -> Result<JsValue, JsError> {
+    let options = parse_options(options)?;
+    let (one, two) = validate_and_parse(yone, ytwo)?;
+    let (one, two) = single_documents(&one, &two, "Merge patch")?;
+    let patch = merge_patch(one, two, &options).map_err(|e| JsError::new(&e.to_string()))?;
+    patch
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|e| JsError::new(&format!("Serialization error: {e}")))
+}
+
+/// Apply a patch to a single YAML document and return the patched YAML.
+/// The patch is either an RFC 6902 operation array or a diff array as
+/// returned by `compute_diff`, given as a JS value or a JSON/YAML string.
+/// A diff is applied with a check of every value it changes, so it fails
+/// on a document that has drifted from the diff's left side.
+#[wasm_bindgen]
+pub fn apply_patch(yaml: &str, patch: JsValue) -> Result<String, JsError> {
+    let documents = read_yaml(yaml).map_err(|e| JsError::new(&format_parse_error("YAML", &e)))?;
+    let [document] = documents.as_slice() else {
+        return Err(JsError::new("A patch applies to exactly one document"));
+    };
+    let patch = match patch.as_string() {
+        Some(text) => serde_yml::from_str(&text)
+            .map_err(|e| JsError::new(&format_parse_error("PATCH", &e)))?,
+        None => serde_wasm_bindgen::from_value(patch)
+            .map_err(|e| JsError::new(&format!("Invalid patch: {e}")))?,
+    };
+    let patch = apply::Patch::from_value(patch)
+        .map_err(|e| JsError::new(&format!("Invalid patch: {e}")))?;
+    let patched = patch
+        .apply(document)
+        .map_err(|e| JsError::new(&e.to_string()))?;
+    serde_yml::to_string(&patched).map_err(|e| JsError::new(&format!("Serialization error: {e}")))
+}
+
+#[derive(Serialize)]
+struct JsConflict {
+    path: String,
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base: Option<serde_yml::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ours: Option<serde_yml::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    theirs: Option<serde_yml::Value>,
+}
+
+#[derive(Serialize)]
+struct JsMerge {
+    merged: String,
+    conflicts: Vec<JsConflict>,
+}
+
+fn parse_single(label: &str, yaml: &str) -> Result<serde_yml::Value, JsError> {
+    let documents = read_yaml(yaml).map_err(|e| JsError::new(&format_parse_error(label, &e)))?;
+    match <[_; 1]>::try_from(documents) {
+        Ok([document]) => Ok(document),
+        Err(_) => Err(JsError::new(&format!(
+            "[{label}] Error: a merge needs exactly one document"
+        ))),
+    }
+}
+
+/// Three-way merge of YAML documents. Returns `{ merged, conflicts }`:
+/// the merged YAML, holding our version wherever both sides changed the
+/// same value, and one entry per conflict with its JSON Pointer path, its
+/// kind (`modify/modify`, `delete/modify` or `add/add`) and the three
+/// values, absent ones omitted.
+#[wasm_bindgen]
+pub fn compute_three_way_merge(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    options: JsValue,
+) -> Result<JsValue, JsError> {
+    let options = parse_options(options)?;
+    let base = parse_single("BASE", base)?;
+    let ours = parse_single("OURS", ours)?;
+    let theirs = parse_single("THEIRS", theirs)?;
+    let result = merge::three_way_merge(&base, &ours, &theirs, &options)
+        .map_err(|e| JsError::new(&format!("Diff error: {e}")))?;
+    let merged = serde_yml::to_string(&result.merged)
+        .map_err(|e| JsError::new(&format!("Serialization error: {e}")))?;
+    let conflicts = result
+        .conflicts
+        .into_iter()
+        .map(|c| JsConflict {
+            path: path::json_pointer(&c.path),
+            kind: c.kind.to_string(),
+            base: c.base,
+            ours: c.ours,
+            theirs: c.theirs,
+        })
+        .collect();
+    JsMerge { merged, conflicts }
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|e| JsError::new(&format!("Serialization error: {e}")))
+}